@@ -4,23 +4,338 @@
 
 #![allow(dead_code)] // Just while half-implemented.
 
+use std::cell::Cell;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::place::Place;
 use crate::value::Value;
 
+/// A statement.
+#[derive(Debug, PartialEq)]
+pub enum Stmt {
+    /// An expression evaluated for its side effects.
+    Expression(Expr),
+    /// Print the value of an expression followed by a newline, or just a newline if there's
+    /// no expression.
+    Print {
+        /// The place of the `print` keyword.
+        place: Place,
+        value: Option<Expr>,
+    },
+    /// Declare a function.
+    Function(Rc<FunctionDecl>),
+    /// Declare a class and its methods.
+    Class {
+        name: String,
+        /// The variable naming the class this one inherits from, if any.
+        superclass: Option<Expr>,
+        methods: Vec<Rc<FunctionDecl>>,
+    },
+    /// A sequence of statements in a new scope.
+    Block(Vec<Stmt>),
+    /// Run `then_branch` if `cond` is truthy, and otherwise `else_branch` if there is one.
+    If {
+        cond: Expr,
+        then_branch: Box<Stmt>,
+        else_branch: Option<Box<Stmt>>,
+    },
+    /// Run `body` repeatedly for as long as `cond` is truthy.
+    ///
+    /// If there's an `increment`, from a `for` loop, it's evaluated after each run of the
+    /// body, including after a `continue`.
+    While {
+        cond: Expr,
+        body: Box<Stmt>,
+        increment: Option<Expr>,
+    },
+    /// Return from the innermost function, with a value or else nil.
+    Return {
+        /// The place of the `return` keyword.
+        place: Place,
+        value: Option<Expr>,
+    },
+    /// Leave the innermost loop.
+    Break {
+        /// The place of the `break` keyword.
+        place: Place,
+    },
+    /// Skip the rest of the body of the innermost loop.
+    Continue {
+        /// The place of the `continue` keyword.
+        place: Place,
+    },
+    /// Declare a variable, initialized to nil if there's no initializer.
+    ///
+    /// A variable declared with `const` can't be assigned to after it's declared.
+    Var {
+        name: String,
+        /// The place of the variable name.
+        place: Place,
+        initializer: Option<Expr>,
+        constant: bool,
+    },
+}
+
+impl Stmt {
+    /// Format this statement as an indented tree, like [Expr::tree].
+    pub fn tree(&self) -> String {
+        let mut out = String::new();
+        self.write_tree(&mut out, 0);
+        out
+    }
+
+    fn write_tree(&self, out: &mut String, depth: usize) {
+        let mut exprs: Vec<&Expr> = Vec::new();
+        let mut stmts: Vec<&Stmt> = Vec::new();
+        let label = match self {
+            Stmt::Expression(expr) => {
+                exprs.push(expr);
+                "Expression".to_owned()
+            }
+            Stmt::Print { value, .. } => {
+                exprs.extend(value);
+                "Print".to_owned()
+            }
+            Stmt::Function(decl) => {
+                stmts.extend(&decl.body);
+                format!("Function {}({})", decl.name, decl.params.join(", "))
+            }
+            Stmt::Class {
+                name,
+                superclass,
+                methods,
+            } => {
+                out.push_str(&"  ".repeat(depth));
+                out.push_str(&format!("Class {}\n", name));
+                if let Some(superclass) = superclass {
+                    superclass.write_tree(out, depth + 1);
+                }
+                for method in methods {
+                    Stmt::Function(Rc::clone(method)).write_tree(out, depth + 1);
+                }
+                return;
+            }
+            Stmt::Block(body) => {
+                stmts.extend(body);
+                "Block".to_owned()
+            }
+            Stmt::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                exprs.push(cond);
+                stmts.push(then_branch);
+                stmts.extend(else_branch.as_deref());
+                "If".to_owned()
+            }
+            Stmt::While {
+                cond,
+                body,
+                increment,
+            } => {
+                exprs.push(cond);
+                exprs.extend(increment);
+                stmts.push(body);
+                "While".to_owned()
+            }
+            Stmt::Return { value, .. } => {
+                exprs.extend(value);
+                "Return".to_owned()
+            }
+            Stmt::Break { .. } => "Break".to_owned(),
+            Stmt::Continue { .. } => "Continue".to_owned(),
+            Stmt::Var {
+                name,
+                initializer,
+                constant,
+                ..
+            } => {
+                exprs.extend(initializer);
+                format!("{} {}", if *constant { "Const" } else { "Var" }, name)
+            }
+        };
+        out.push_str(&"  ".repeat(depth));
+        out.push_str(&label);
+        out.push('\n');
+        for expr in exprs {
+            expr.write_tree(out, depth + 1);
+        }
+        for stmt in stmts {
+            stmt.write_tree(out, depth + 1);
+        }
+    }
+}
+
+/// The declaration of a function, which is shared by every function value made from it.
+#[derive(Debug, PartialEq)]
+pub struct FunctionDecl {
+    /// The function's name, or empty for an anonymous function.
+    pub name: String,
+    pub params: Vec<String>,
+    pub body: Vec<Stmt>,
+}
+
+/// An expression.
+///
+/// Operator expressions carry the [Place] of their operator, for reporting runtime errors.
 #[derive(Debug, PartialEq)]
 pub enum Expr {
     Literal(Value),
+    /// A reference to a variable, with the place where it's used.
+    Variable {
+        name: String,
+        place: Place,
+        /// The number of scopes out from here to where the variable is declared, set by
+        /// the resolver, or None for a global.
+        depth: Cell<Option<usize>>,
+    },
+    /// Assign a new value to an existing variable, yielding the value.
+    Assign {
+        name: String,
+        /// The place of the variable name.
+        place: Place,
+        /// The number of scopes out to the variable, as for [Expr::Variable].
+        depth: Cell<Option<usize>>,
+        value: Box<Expr>,
+    },
+    /// Read a property of an object, with the place of the property name.
+    Get {
+        object: Box<Expr>,
+        name: String,
+        place: Place,
+    },
+    /// Set a property of an object, yielding the value.
+    Set {
+        object: Box<Expr>,
+        name: String,
+        /// The place of the property name.
+        place: Place,
+        value: Box<Expr>,
+    },
+    /// The instance a method was called on.
+    This {
+        place: Place,
+        /// The number of scopes out to where `this` is bound, set by the resolver.
+        depth: Cell<Option<usize>>,
+    },
+    /// A method of the superclass, bound to `this`, with the place of `super`.
+    Super {
+        place: Place,
+        method: String,
+        /// The number of scopes out to where `super` is bound, set by the resolver.
+        depth: Cell<Option<usize>>,
+    },
+    /// An anonymous function, which makes a new closure each time it's evaluated.
+    Function(Rc<FunctionDecl>),
+    /// Call a function, with the place of the opening parenthesis.
+    Call {
+        callee: Box<Expr>,
+        paren: Place,
+        args: Vec<Expr>,
+    },
     Grouping {
         expr: Box<Expr>,
     },
     Unary {
         op: UnaryOp,
+        place: Place,
         expr: Box<Expr>,
     },
     Binary {
         op: BinaryOp,
+        place: Place,
         left: Box<Expr>,
         right: Box<Expr>,
     },
+    /// A conditional expression `cond ? then_expr : else_expr`, with the place of the `?`.
+    Ternary {
+        place: Place,
+        cond: Box<Expr>,
+        then_expr: Box<Expr>,
+        else_expr: Box<Expr>,
+    },
+    /// A logical operator, which might not evaluate its right operand.
+    Logical {
+        op: LogicalOp,
+        place: Place,
+        left: Box<Expr>,
+        right: Box<Expr>,
+    },
+    /// Comparisons chained as in mathematics, so that `a < b < c` means `a < b and b < c`,
+    /// but evaluating `b` only once.
+    ///
+    /// Each of `rest` is an operator, its place, and its right operand. This is only made
+    /// by the parser with [crate::parse::ParseOptions::math_chains].
+    Chain {
+        first: Box<Expr>,
+        rest: Vec<(BinaryOp, Place, Expr)>,
+    },
+}
+
+impl Expr {
+    /// Format this expression as an indented tree, with one node per line and the operands
+    /// of each node indented under it.
+    pub fn tree(&self) -> String {
+        let mut out = String::new();
+        self.write_tree(&mut out, 0);
+        out
+    }
+
+    fn write_tree(&self, out: &mut String, depth: usize) {
+        use Expr::*;
+        if let Function(decl) = self {
+            // Show the body like a function declaration.
+            Stmt::Function(Rc::clone(decl)).write_tree(out, depth);
+            return;
+        }
+        let label = match self {
+            Literal(Value::String(s)) => format!("Literal {:?}", s),
+            Literal(value) => format!("Literal {}", value),
+            Variable { name, .. } => format!("Variable {}", name),
+            Assign { name, .. } => format!("Assign {}", name),
+            Get { name, .. } => format!("Get {}", name),
+            Set { name, .. } => format!("Set {}", name),
+            This { .. } => "This".to_owned(),
+            Super { method, .. } => format!("Super {}", method),
+            Function(_) => unreachable!(),
+            Call { .. } => "Call".to_owned(),
+            Grouping { .. } => "Grouping".to_owned(),
+            Unary { op, .. } => format!("Unary {}", op),
+            Binary { op, .. } => format!("Binary {}", op),
+            Ternary { .. } => "Ternary".to_owned(),
+            Logical { op, .. } => format!("Logical {}", op),
+            Chain { rest, .. } => {
+                let ops: Vec<String> = rest.iter().map(|(op, ..)| op.to_string()).collect();
+                format!("Chain {}", ops.join(" "))
+            }
+        };
+        out.push_str(&"  ".repeat(depth));
+        out.push_str(&label);
+        out.push('\n');
+        let children: Vec<&Expr> = match self {
+            Literal(_) | Variable { .. } | This { .. } | Super { .. } | Function(_) => vec![],
+            Grouping { expr } | Unary { expr, .. } => vec![expr],
+            Assign { value, .. } => vec![value],
+            Get { object, .. } => vec![object],
+            Set { object, value, .. } => vec![object, value],
+            Call { callee, args, .. } => std::iter::once(callee.as_ref()).chain(args).collect(),
+            Binary { left, right, .. } | Logical { left, right, .. } => vec![left, right],
+            Ternary {
+                cond,
+                then_expr,
+                else_expr,
+                ..
+            } => vec![cond, then_expr, else_expr],
+            Chain { first, rest } => std::iter::once(first.as_ref())
+                .chain(rest.iter().map(|(_, _, operand)| operand))
+                .collect(),
+        };
+        for child in children {
+            child.write_tree(out, depth + 1);
+        }
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -29,6 +344,16 @@ pub enum UnaryOp {
     Negative,
 }
 
+impl fmt::Display for UnaryOp {
+    /// Show the operator as it's written in source.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            UnaryOp::Not => "!",
+            UnaryOp::Negative => "-",
+        })
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub enum BinaryOp {
     EqualEqual,
@@ -41,4 +366,49 @@ pub enum BinaryOp {
     Minus,
     Multiply,
     Divide,
+    /// The remainder after division, with the sign of the dividend.
+    Modulo,
+    /// Raise the left operand to the power of the right.
+    Power,
+    /// Evaluate both operands, and yield the right one.
+    Comma,
+}
+
+impl fmt::Display for BinaryOp {
+    /// Show the operator as it's written in source.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use BinaryOp::*;
+        let s = match self {
+            EqualEqual => "==",
+            NotEqual => "!=",
+            LessThan => "<",
+            LessEqual => "<=",
+            GreaterThan => ">",
+            GreaterEqual => ">=",
+            Plus => "+",
+            Minus => "-",
+            Multiply => "*",
+            Divide => "/",
+            Modulo => "%",
+            Power => "**",
+            Comma => ",",
+        };
+        f.write_str(s)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum LogicalOp {
+    And,
+    Or,
+}
+
+impl fmt::Display for LogicalOp {
+    /// Show the operator as it's written in source.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LogicalOp::And => "and",
+            LogicalOp::Or => "or",
+        })
+    }
 }
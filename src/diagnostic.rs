@@ -0,0 +1,98 @@
+// Copyright 2021 Martin Pool
+
+//! Show the source around the place where an error occurred.
+
+use crate::place::Place;
+
+/// Render the line of `source` containing `place`, with a caret under the place.
+///
+/// The line is prefixed by its number, in the style of rustc:
+///
+/// ```text
+/// 7 | foo(a | b);
+///   |       ^
+/// ```
+///
+/// The result ends with a newline. It's empty if the place is not within `source`, which
+/// can happen for a runtime error in a function declared in some earlier source.
+pub fn source_snippet(source: &str, place: Place) -> String {
+    let is_newline = |c: char| c == '\n' || c == '\r';
+    let offset = place.offset;
+    if offset > source.len() || !source.is_char_boundary(offset) {
+        return String::new();
+    }
+    let line_start = source[..offset].rfind(is_newline).map_or(0, |i| i + 1);
+    let line_end = source[offset..]
+        .find(is_newline)
+        .map_or(source.len(), |i| offset + i);
+    // Copy tabs from the source line so that the caret lines up however they're shown.
+    let indent: String = source[line_start..offset]
+        .chars()
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let line_number = place.line.to_string();
+    let gutter = " ".repeat(line_number.len());
+    format!(
+        "{} | {}\n{} | {}^\n",
+        line_number,
+        &source[line_start..line_end],
+        gutter,
+        indent
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::source_snippet;
+    use crate::place::Place;
+
+    #[test]
+    fn caret_under_column() {
+        let source = "var a = 1;\nfoo(a | b);\nprint a;\n";
+        assert_eq!(
+            source_snippet(source, Place::new(2, 7, 17)),
+            "2 | foo(a | b);\n  |       ^\n"
+        );
+    }
+
+    #[test]
+    fn gutter_is_as_wide_as_line_number() {
+        let source = format!("{}@", "\n".repeat(11));
+        assert_eq!(
+            source_snippet(&source, Place::new(12, 1, 11)),
+            "12 | @\n   | ^\n"
+        );
+    }
+
+    #[test]
+    fn tabs_are_kept_before_caret() {
+        assert_eq!(
+            source_snippet("\tx = é + #;", Place::new(1, 16, 10)),
+            "1 | \tx = é + #;\n  | \t        ^\n"
+        );
+    }
+
+    #[test]
+    fn place_at_end_of_source() {
+        assert_eq!(
+            source_snippet("print 1 +", Place::new(1, 10, 9)),
+            "1 | print 1 +\n  |          ^\n"
+        );
+    }
+
+    #[test]
+    fn place_outside_source_has_no_snippet() {
+        assert_eq!(source_snippet("é", Place::new(1, 2, 1)), "");
+        assert_eq!(source_snippet("a", Place::new(3, 1, 20)), "");
+    }
+
+    #[test]
+    fn crlf_is_not_shown() {
+        assert_eq!(
+            source_snippet("a;\r\nb c;\r\n", Place::new(2, 3, 6)),
+            "2 | b c;\n  |   ^\n"
+        );
+    }
+}
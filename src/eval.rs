@@ -2,70 +2,2076 @@
 
 //! Evaluate Lox source.
 
-use anyhow::{anyhow, Result};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{self, Write};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
 
 use crate::ast;
-use crate::lex::{lex, Token};
+use crate::lex::{self, tokenize};
+use crate::native::natives;
 use crate::parse;
-use crate::value::Value;
+use crate::place::Place;
+use crate::resolve;
+use crate::value::{Class, Function, Instance, NativeFn, Value};
+
+/// The deepest that calls can be nested before a program fails with a stack overflow.
+///
+/// Each call also uses a good deal of the native stack, especially in a debug build, so
+/// the thread running the interpreter needs a large stack to reach this depth.
+pub const MAX_CALL_DEPTH: usize = 1000;
+
+/// The deepest that calls to the `eval` native function can be nested.
+pub const MAX_EVAL_DEPTH: usize = 100;
+
+/// A callback told the name of a function, and either the arguments it's called with or
+/// the value it returns.
+pub type CallHook<'o> = Box<dyn FnMut(&str, &[Value]) + 'o>;
+
+/// An error raised while evaluating an expression, such as an operand of the wrong type.
+#[derive(Debug, PartialEq)]
+pub struct RuntimeError {
+    /// The place of the operator or name whose evaluation failed.
+    pub place: Place,
+    pub message: String,
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] Error: {}.", self.place, self.message)
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+/// An error found in source code before running it.
+#[derive(Debug, PartialEq)]
+pub enum CompileError {
+    Lex(lex::Error),
+    Parse(parse::Error),
+    Resolve(resolve::Error),
+}
+
+impl CompileError {
+    /// The place of the error, or None if it's at the end of the input.
+    pub fn place(&self) -> Option<Place> {
+        match self {
+            CompileError::Lex(err) => Some(err.place),
+            CompileError::Parse(err) => err.place,
+            CompileError::Resolve(err) => Some(err.place),
+        }
+    }
+
+    /// The phase that found the error, as used in a summary of errors.
+    fn phase(&self) -> &'static str {
+        match self {
+            CompileError::Lex(_) => "lex",
+            CompileError::Parse(_) => "parse",
+            CompileError::Resolve(_) => "resolve",
+        }
+    }
+
+    /// The description of the error, without its place or code.
+    fn message(&self) -> String {
+        match self {
+            CompileError::Lex(err) => err.kind.to_string(),
+            CompileError::Parse(err) => err.kind.to_string(),
+            CompileError::Resolve(err) => err.kind.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompileError::Lex(err) => err.fmt(f),
+            CompileError::Parse(err) => err.fmt(f),
+            CompileError::Resolve(err) => err.fmt(f),
+        }
+    }
+}
+
+/// All the errors found in some source code before running it, which all come from the
+/// same phase: the resolver doesn't run if the source didn't parse, for example.
+///
+/// This displays as just a summary: the caller can show each error with the source where
+/// it was found.
+#[derive(Debug, PartialEq)]
+pub struct CompileErrors(pub Vec<CompileError>);
+
+impl fmt::Display for CompileErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let phase = self.0.first().map_or("compile", CompileError::phase);
+        write!(f, "{} {} error(s)", self.0.len(), phase)
+    }
+}
+
+impl std::error::Error for CompileErrors {}
+
+/// Variables and their values in one scope, and the scope enclosing it.
+///
+/// Scopes are shared, because a function keeps the scope where it was declared alive for
+/// as long as the function exists.
+#[derive(Debug, Default)]
+pub struct Environment {
+    values: HashMap<String, Value>,
+    enclosing: Option<Rc<RefCell<Environment>>>,
+}
+
+impl Environment {
+    /// Make a new empty scope inside `enclosing`.
+    pub fn with_enclosing(enclosing: Rc<RefCell<Environment>>) -> Environment {
+        Environment {
+            values: HashMap::new(),
+            enclosing: Some(enclosing),
+        }
+    }
+
+    /// Define a variable in this scope, replacing any existing variable of the same name in
+    /// this scope.
+    pub fn define(&mut self, name: &str, value: Value) {
+        self.values.insert(name.to_owned(), value);
+    }
+
+    /// Assign a new value to the nearest variable of this name, returning false if it's not
+    /// defined in this or any enclosing scope.
+    pub fn assign(&mut self, name: &str, value: Value) -> bool {
+        match self.values.get_mut(name) {
+            Some(slot) => {
+                *slot = value;
+                true
+            }
+            None => match &self.enclosing {
+                Some(enclosing) => enclosing.borrow_mut().assign(name, value),
+                None => false,
+            },
+        }
+    }
+
+    /// Get the value of the nearest variable of this name, or None if it's not defined.
+    pub fn get(&self, name: &str) -> Option<Value> {
+        match self.values.get(name) {
+            Some(value) => Some(value.clone()),
+            None => self.enclosing.as_ref().and_then(|e| e.borrow().get(name)),
+        }
+    }
 
-pub struct Interpreter {}
+    /// Get the value of a variable in the scope `distance` steps out from this one.
+    pub fn get_at(&self, distance: usize, name: &str) -> Option<Value> {
+        if distance == 0 {
+            self.values.get(name).cloned()
+        } else {
+            self.enclosing
+                .as_ref()
+                .and_then(|e| e.borrow().get_at(distance - 1, name))
+        }
+    }
+
+    /// Assign to a variable in the scope `distance` steps out from this one, returning false
+    /// if it's not defined there.
+    pub fn assign_at(&mut self, distance: usize, name: &str, value: Value) -> bool {
+        if distance == 0 {
+            match self.values.get_mut(name) {
+                Some(slot) => {
+                    *slot = value;
+                    true
+                }
+                None => false,
+            }
+        } else {
+            match &self.enclosing {
+                Some(enclosing) => enclosing.borrow_mut().assign_at(distance - 1, name, value),
+                None => false,
+            }
+        }
+    }
+}
+
+/// Runs Lox code, keeping global variables from one call to [Interpreter::eval] to the next.
+///
+/// The output of `print` statements goes to a writer that lives for `'o`.
+pub struct Interpreter<'o> {
+    /// The innermost scope, which is the globals except while running a block or function.
+    env: Rc<RefCell<Environment>>,
+    /// The outermost scope, where variables the resolver didn't find locally are looked up.
+    globals: Rc<RefCell<Environment>>,
+    /// Where `print` writes.
+    out: Box<dyn Write + 'o>,
+    /// Time spent in each phase so far.
+    timings: Timings,
+    /// The names of global variables declared with `const` so far.
+    global_constants: HashSet<String>,
+    /// The number of calls currently running.
+    call_depth: usize,
+    /// The number of calls to the `eval` native function currently running.
+    eval_depth: usize,
+    /// Called before every function call, with its arguments.
+    on_call: Option<CallHook<'o>>,
+    /// Called after every function call that returns without an error, with the value
+    /// it returns.
+    on_return: Option<CallHook<'o>>,
+    /// Non-standard language options used when parsing source code.
+    parse_options: parse::ParseOptions,
+}
 
-impl Interpreter {
+/// The total time spent in each phase of running code, across all calls to
+/// [Interpreter::eval].
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Timings {
+    pub lex: Duration,
+    pub parse: Duration,
+    pub resolve: Duration,
+    pub eval: Duration,
+}
+
+impl Interpreter<'static> {
+    /// Make a new interpreter that prints to stdout, whose globals are just the native
+    /// functions.
     pub fn new() -> Self {
-        Interpreter {}
+        Interpreter::with_output(Box::new(io::stdout()))
+    }
+}
+
+impl Default for Interpreter<'static> {
+    fn default() -> Self {
+        Interpreter::new()
+    }
+}
+
+impl<'o> Interpreter<'o> {
+    /// Make a new interpreter that prints to `out`.
+    pub fn with_output(out: Box<dyn Write + 'o>) -> Self {
+        let globals = native_globals();
+        Interpreter {
+            env: Rc::clone(&globals),
+            globals,
+            out,
+            timings: Timings::default(),
+            global_constants: HashSet::new(),
+            call_depth: 0,
+            eval_depth: 0,
+            on_call: None,
+            on_return: None,
+            parse_options: parse::ParseOptions::default(),
+        }
+    }
+
+    /// Forget all the global variables, functions, and classes defined so far, leaving
+    /// just the native functions.
+    pub fn reset(&mut self) {
+        self.globals = native_globals();
+        self.env = Rc::clone(&self.globals);
+        self.global_constants.clear();
+    }
+
+    /// Parse source code passed to later calls with non-standard `options`.
+    pub fn set_parse_options(&mut self, options: parse::ParseOptions) {
+        self.parse_options = options;
+    }
+
+    /// Call `hook` with the name and arguments of every function, native function, or
+    /// class, before it's called.
+    pub fn set_on_call(&mut self, hook: CallHook<'o>) {
+        self.on_call = Some(hook);
+    }
+
+    /// Call `hook` with the name of every function, native function, or class, and a
+    /// slice holding just the value it returned, after it returns.
+    pub fn set_on_return(&mut self, hook: CallHook<'o>) {
+        self.on_return = Some(hook);
+    }
+
+    /// Return the total time spent in each phase so far.
+    pub fn timings(&self) -> Timings {
+        self.timings
+    }
+
+    /// Run some source code.
+    ///
+    /// Returns the value of the last statement if it's an expression statement, so that
+    /// the command line and REPL can show it, or otherwise None.
+    pub fn eval(&mut self, source: &str) -> Result<Option<Value>> {
+        let stmts = self.compile(source)?;
+        let start = Instant::now();
+        let result = self.run(&stmts);
+        self.timings.eval += start.elapsed();
+        // Show everything printed so far, even if there was an error, before the caller
+        // prints anything else.
+        self.out.flush()?;
+        Ok(result?)
+    }
+
+    /// Run source code given to the `eval` native function, in the global scope whichever
+    /// scope it's called from, returning the value of the last expression or nil.
+    ///
+    /// Errors are returned as a message for the native function to report.
+    pub(crate) fn eval_nested(&mut self, source: &str) -> Result<Value, String> {
+        if self.eval_depth >= MAX_EVAL_DEPTH {
+            return Err(format!("eval: nested more than {} deep", MAX_EVAL_DEPTH));
+        }
+        let env = std::mem::replace(&mut self.env, Rc::clone(&self.globals));
+        self.eval_depth += 1;
+        let result = self.eval(source);
+        self.eval_depth -= 1;
+        self.env = env;
+        match result {
+            Ok(value) => Ok(value.unwrap_or(Value::Nil)),
+            Err(err) => Err(match err.downcast::<RuntimeError>() {
+                // An error from an eval nested inside this one already says where it was.
+                Ok(err) if err.message.starts_with("eval: ") => err.message,
+                Ok(err) => format!("eval: {} at {}", err.message, err.place),
+                Err(err) => match err.downcast::<CompileErrors>() {
+                    Ok(errors) => {
+                        // An error at the end of the input already says so.
+                        let first = &errors.0[0];
+                        match first.place() {
+                            Some(place) => format!("eval: {} at {}", first.message(), place),
+                            None => format!("eval: {}", first.message()),
+                        }
+                    }
+                    Err(err) => format!("eval: {}", err),
+                },
+            }),
+        }
+    }
+
+    /// Check that some source code lexes, parses, and resolves, without running it.
+    ///
+    /// Errors are returned as [CompileErrors], as for [Interpreter::eval].
+    pub fn check(&mut self, source: &str) -> Result<()> {
+        self.compile(source)?;
+        Ok(())
     }
 
-    pub fn eval(&mut self, source: &str) -> Result<Value> {
-        let results = lex(source);
-        dbg!(&results);
-        // TODO: Print all errors; return the first one (or all of them?).
-        let tokens: Vec<Token> = results.into_iter().map(Result::unwrap).collect();
+    /// Lex, parse, and resolve some source, returning all the errors from the first phase
+    /// that finds any.
+    fn compile(&mut self, source: &str) -> Result<Vec<ast::Stmt>, CompileErrors> {
+        let start = Instant::now();
+        let tokens = tokenize(source);
+        self.timings.lex += start.elapsed();
+        let tokens = match tokens {
+            Ok(tokens) => tokens,
+            Err(errors) => {
+                return Err(CompileErrors(
+                    errors.into_iter().map(CompileError::Lex).collect(),
+                ))
+            }
+        };
 
-        let (expr, rest) = parse::parse_expr(&tokens)?;
-        dbg!(&expr);
-        assert!(rest.is_empty());
+        let start = Instant::now();
+        let (stmts, errors) = parse::parse_program_with_options(&tokens, self.parse_options);
+        self.timings.parse += start.elapsed();
+        if !errors.is_empty() {
+            return Err(CompileErrors(
+                errors.into_iter().map(CompileError::Parse).collect(),
+            ));
+        }
 
-        let value = expr.eval()?;
-        dbg!(&value);
+        let start = Instant::now();
+        let errors = resolve::resolve_with_constants(&stmts, &mut self.global_constants);
+        self.timings.resolve += start.elapsed();
+        if !errors.is_empty() {
+            return Err(CompileErrors(
+                errors.into_iter().map(CompileError::Resolve).collect(),
+            ));
+        }
+        Ok(stmts)
+    }
 
+    /// Run parsed and resolved statements, returning the value of the last one if it's an
+    /// expression statement.
+    fn run(&mut self, stmts: &[ast::Stmt]) -> Result<Option<Value>, RuntimeError> {
+        let mut value = None;
+        for stmt in stmts {
+            value = match stmt {
+                ast::Stmt::Expression(expr) => Some(expr.eval(self)?),
+                _ => {
+                    self.execute(stmt)?;
+                    None
+                }
+            };
+        }
         Ok(value)
     }
+
+    /// Execute one statement, returning whether it finished normally or by a jump out of a
+    /// loop or function.
+    fn execute(&mut self, stmt: &ast::Stmt) -> Result<Flow, RuntimeError> {
+        match stmt {
+            ast::Stmt::Expression(expr) => {
+                expr.eval(self)?;
+            }
+            ast::Stmt::Print { place, value } => {
+                let text = match value {
+                    Some(expr) => expr.eval(self)?.to_string(),
+                    None => String::new(),
+                };
+                writeln!(self.out, "{}", text).map_err(|err| RuntimeError {
+                    place: *place,
+                    message: format!("failed to write output: {}", err),
+                })?;
+            }
+            ast::Stmt::Var {
+                name, initializer, ..
+            } => {
+                let value = match initializer {
+                    Some(expr) => expr.eval(self)?,
+                    None => Value::Nil,
+                };
+                self.env.borrow_mut().define(name, value);
+            }
+            ast::Stmt::Function(decl) => {
+                let function = Function {
+                    decl: Rc::clone(decl),
+                    closure: Rc::clone(&self.env),
+                    is_initializer: false,
+                };
+                self.env
+                    .borrow_mut()
+                    .define(&decl.name, Value::Function(Rc::new(function)));
+            }
+            ast::Stmt::Class {
+                name,
+                superclass,
+                methods,
+            } => {
+                let superclass = match superclass {
+                    Some(expr) => match expr.eval(self)? {
+                        Value::Class(class) => Some(class),
+                        _ => {
+                            let place = match expr {
+                                ast::Expr::Variable { place, .. } => *place,
+                                _ => unreachable!("superclass is always a variable"),
+                            };
+                            return Err(RuntimeError {
+                                place,
+                                message: "superclass must be a class".to_owned(),
+                            });
+                        }
+                    },
+                    None => None,
+                };
+                // Methods of a subclass close over a new scope where `super` is bound.
+                let closure = match &superclass {
+                    Some(superclass) => {
+                        let mut env = Environment::with_enclosing(Rc::clone(&self.env));
+                        env.define("super", Value::Class(Rc::clone(superclass)));
+                        Rc::new(RefCell::new(env))
+                    }
+                    None => Rc::clone(&self.env),
+                };
+                let methods = methods
+                    .iter()
+                    .map(|decl| {
+                        let method = Function {
+                            decl: Rc::clone(decl),
+                            closure: Rc::clone(&closure),
+                            is_initializer: decl.name == "init",
+                        };
+                        (decl.name.clone(), Rc::new(method))
+                    })
+                    .collect();
+                let class = Class {
+                    name: name.clone(),
+                    superclass,
+                    methods,
+                };
+                self.env
+                    .borrow_mut()
+                    .define(name, Value::Class(Rc::new(class)));
+            }
+            ast::Stmt::Block(stmts) => {
+                let env = Environment::with_enclosing(Rc::clone(&self.env));
+                return self.execute_block(stmts, env);
+            }
+            ast::Stmt::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                if cond.eval(self)?.is_truthy() {
+                    return self.execute(then_branch);
+                } else if let Some(else_branch) = else_branch {
+                    return self.execute(else_branch);
+                }
+            }
+            ast::Stmt::While {
+                cond,
+                body,
+                increment,
+            } => {
+                while cond.eval(self)?.is_truthy() {
+                    match self.execute(body)? {
+                        Flow::Break => break,
+                        Flow::Normal | Flow::Continue => (),
+                        flow @ Flow::Return(_) => return Ok(flow),
+                    }
+                    if let Some(increment) = increment {
+                        increment.eval(self)?;
+                    }
+                }
+            }
+            ast::Stmt::Return { value, .. } => {
+                let value = match value {
+                    Some(expr) => expr.eval(self)?,
+                    None => Value::Nil,
+                };
+                return Ok(Flow::Return(value));
+            }
+            ast::Stmt::Break { .. } => return Ok(Flow::Break),
+            ast::Stmt::Continue { .. } => return Ok(Flow::Continue),
+        }
+        Ok(Flow::Normal)
+    }
+
+    /// Execute statements in the scope `env`, and then go back to the current scope, even if
+    /// there's an error.
+    ///
+    /// The block stops early if a statement jumps out of it.
+    fn execute_block(
+        &mut self,
+        stmts: &[ast::Stmt],
+        env: Environment,
+    ) -> Result<Flow, RuntimeError> {
+        let outer = std::mem::replace(&mut self.env, Rc::new(RefCell::new(env)));
+        let mut result = Ok(Flow::Normal);
+        for stmt in stmts {
+            result = self.execute(stmt);
+            if result != Ok(Flow::Normal) {
+                break;
+            }
+        }
+        self.env = outer;
+        result
+    }
+
+    /// Call a value with evaluated arguments.
+    ///
+    /// Errors in the call itself, such as the wrong number of arguments, are reported at
+    /// `paren`; errors inside the function's body are reported where they happen.
+    fn call(
+        &mut self,
+        callee: Value,
+        args: Vec<Value>,
+        paren: Place,
+    ) -> Result<Value, RuntimeError> {
+        let error = |message| RuntimeError {
+            place: paren,
+            message,
+        };
+        let callable: &dyn LoxCallable = match &callee {
+            Value::NativeFn(native_fn) => native_fn.as_ref(),
+            Value::Function(function) => function.as_ref(),
+            Value::Class(class) => class,
+            _ => return Err(error("can only call functions and classes".to_owned())),
+        };
+        if args.len() != callable.arity() {
+            return Err(error(format!(
+                "{} expected {} arguments but got {}",
+                callable.name(),
+                callable.arity(),
+                args.len()
+            )));
+        }
+        if self.call_depth >= MAX_CALL_DEPTH {
+            return Err(error("stack overflow".to_owned()));
+        }
+        if let Some(on_call) = &mut self.on_call {
+            on_call(callable.name(), &args);
+        }
+        self.call_depth += 1;
+        let result = callable.call(self, args, paren);
+        self.call_depth -= 1;
+        if let (Some(on_return), Ok(value)) = (&mut self.on_return, &result) {
+            on_return(callable.name(), std::slice::from_ref(value));
+        }
+        result
+    }
+}
+
+/// A global scope holding just the native functions.
+fn native_globals() -> Rc<RefCell<Environment>> {
+    let mut env = Environment::default();
+    for native_fn in natives() {
+        env.define(native_fn.name, Value::NativeFn(native_fn));
+    }
+    Rc::new(RefCell::new(env))
+}
+
+/// Something that can be called from Lox: a native function, a function declared in Lox,
+/// or a class.
+pub trait LoxCallable {
+    /// The name used in error messages about calls.
+    fn name(&self) -> &str;
+
+    /// The number of arguments it must be called with.
+    fn arity(&self) -> usize;
+
+    /// Call it with arguments that have already been checked against the arity.
+    ///
+    /// Errors from a native function are reported at `paren`.
+    fn call(
+        &self,
+        interpreter: &mut Interpreter<'_>,
+        args: Vec<Value>,
+        paren: Place,
+    ) -> Result<Value, RuntimeError>;
+}
+
+impl LoxCallable for NativeFn {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn arity(&self) -> usize {
+        self.arity
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter<'_>,
+        args: Vec<Value>,
+        paren: Place,
+    ) -> Result<Value, RuntimeError> {
+        (self.fun)(interpreter, &args).map_err(|message| RuntimeError {
+            place: paren,
+            message,
+        })
+    }
+}
+
+impl LoxCallable for Function {
+    fn name(&self) -> &str {
+        if self.decl.name.is_empty() {
+            "anonymous function"
+        } else {
+            &self.decl.name
+        }
+    }
+
+    fn arity(&self) -> usize {
+        self.decl.params.len()
+    }
+
+    /// Run the body in a new scope inside the closure, holding the parameters.
+    ///
+    /// An initializer always returns `this`.
+    fn call(
+        &self,
+        interpreter: &mut Interpreter<'_>,
+        args: Vec<Value>,
+        _paren: Place,
+    ) -> Result<Value, RuntimeError> {
+        let mut env = Environment::with_enclosing(Rc::clone(&self.closure));
+        for (param, arg) in self.decl.params.iter().zip(args) {
+            env.define(param, arg);
+        }
+        let flow = interpreter.execute_block(&self.decl.body, env)?;
+        if self.is_initializer {
+            Ok(self
+                .closure
+                .borrow()
+                .get_at(0, "this")
+                .expect("initializer is bound to an instance"))
+        } else if let Flow::Return(value) = flow {
+            Ok(value)
+        } else {
+            Ok(Value::Nil)
+        }
+    }
+}
+
+/// Calling a class makes a new instance and passes the arguments to its `init` method, if
+/// it has one.
+impl LoxCallable for Rc<Class> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn arity(&self) -> usize {
+        self.find_method("init")
+            .map_or(0, |init| init.decl.params.len())
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter<'_>,
+        args: Vec<Value>,
+        paren: Place,
+    ) -> Result<Value, RuntimeError> {
+        let instance = Rc::new(Instance::new(Rc::clone(self)));
+        if let Some(init) = self.find_method("init") {
+            init.bind(Rc::clone(&instance))
+                .call(interpreter, args, paren)?;
+        }
+        Ok(Value::Instance(instance))
+    }
+}
+
+/// How control leaves a statement.
+#[derive(Debug, Clone, PartialEq)]
+enum Flow {
+    /// Carry on to the next statement.
+    Normal,
+    /// Leave the innermost loop.
+    Break,
+    /// Go on to the next iteration of the innermost loop.
+    Continue,
+    /// Return a value from the innermost function.
+    Return(Value),
 }
 
 pub trait Eval {
-    fn eval(&self) -> Result<Value>;
+    fn eval(&self, interpreter: &mut Interpreter<'_>) -> Result<Value, RuntimeError>;
 }
 
 impl Eval for ast::Expr {
-    fn eval(&self) -> Result<Value> {
+    fn eval(&self, interpreter: &mut Interpreter<'_>) -> Result<Value, RuntimeError> {
         use ast::Expr::*;
+        let at = |place: &Place| {
+            let place = *place;
+            move |message| RuntimeError { place, message }
+        };
         match self {
             Literal(value) => Ok(value.clone()),
-            Grouping { expr } => expr.eval(),
-            Unary { op, expr } => apply_unary(op, expr.eval()?),
-            _other => unimplemented!(),
+            Variable { name, place, depth } => match depth.get() {
+                Some(distance) => interpreter.env.borrow().get_at(distance, name),
+                None => interpreter.globals.borrow().get(name),
+            }
+            .ok_or_else(|| format!("undefined variable '{}'", name))
+            .map_err(at(place)),
+            Assign {
+                name,
+                place,
+                depth,
+                value,
+            } => {
+                let value = value.eval(interpreter)?;
+                let assigned = match depth.get() {
+                    Some(distance) => {
+                        interpreter
+                            .env
+                            .borrow_mut()
+                            .assign_at(distance, name, value.clone())
+                    }
+                    None => interpreter.globals.borrow_mut().assign(name, value.clone()),
+                };
+                if assigned {
+                    Ok(value)
+                } else {
+                    Err(at(place)(format!("undefined variable '{}'", name)))
+                }
+            }
+            Get {
+                object,
+                name,
+                place,
+            } => match object.eval(interpreter)? {
+                Value::Instance(instance) => instance
+                    .get(name)
+                    .or_else(|| {
+                        let method = instance.class.find_method(name)?;
+                        Some(Value::Function(Rc::new(method.bind(Rc::clone(&instance)))))
+                    })
+                    .ok_or_else(|| format!("undefined property '{}'", name))
+                    .map_err(at(place)),
+                _ => Err(at(place)("only instances have fields".to_owned())),
+            },
+            Set {
+                object,
+                name,
+                place,
+                value,
+            } => {
+                let instance = match object.eval(interpreter)? {
+                    Value::Instance(instance) => instance,
+                    _ => return Err(at(place)("only instances have fields".to_owned())),
+                };
+                let value = value.eval(interpreter)?;
+                instance.set(name, value.clone());
+                Ok(value)
+            }
+            Function(decl) => Ok(Value::Function(Rc::new(crate::value::Function {
+                decl: Rc::clone(decl),
+                closure: Rc::clone(&interpreter.env),
+                is_initializer: false,
+            }))),
+            This { place, depth } => depth
+                .get()
+                .and_then(|distance| interpreter.env.borrow().get_at(distance, "this"))
+                .ok_or_else(|| "can't use 'this' outside of a class".to_owned())
+                .map_err(at(place)),
+            Super {
+                place,
+                method,
+                depth,
+            } => {
+                // `this` is bound in the scope just inside the one where `super` is bound.
+                let (superclass, this) = match depth.get() {
+                    Some(distance) if distance > 0 => {
+                        let env = interpreter.env.borrow();
+                        (
+                            env.get_at(distance, "super"),
+                            env.get_at(distance - 1, "this"),
+                        )
+                    }
+                    _ => (None, None),
+                };
+                match (superclass, this) {
+                    (Some(Value::Class(superclass)), Some(Value::Instance(instance))) => superclass
+                        .find_method(method)
+                        .map(|method| Value::Function(Rc::new(method.bind(instance))))
+                        .ok_or_else(|| format!("undefined property '{}'", method))
+                        .map_err(at(place)),
+                    _ => Err(at(place)(
+                        "can't use 'super' outside of a subclass".to_owned(),
+                    )),
+                }
+            }
+            Call {
+                callee,
+                paren,
+                args,
+            } => {
+                let callee = callee.eval(interpreter)?;
+                let args = args
+                    .iter()
+                    .map(|arg| arg.eval(interpreter))
+                    .collect::<Result<Vec<Value>, RuntimeError>>()?;
+                interpreter.call(callee, args, *paren)
+            }
+            Grouping { expr } => expr.eval(interpreter),
+            Unary { op, place, expr } => {
+                apply_unary(op, expr.eval(interpreter)?).map_err(at(place))
+            }
+            Binary {
+                op,
+                place,
+                left,
+                right,
+            } => {
+                let left = left.eval(interpreter)?;
+                let right = right.eval(interpreter)?;
+                apply_binary(op, left, right).map_err(at(place))
+            }
+            // The right operand is only evaluated if the left doesn't decide the result, and
+            // the result is the value of whichever operand decided it, not just a bool.
+            Logical {
+                op, left, right, ..
+            } => {
+                let left = left.eval(interpreter)?;
+                let decided = match op {
+                    ast::LogicalOp::And => !left.is_truthy(),
+                    ast::LogicalOp::Or => left.is_truthy(),
+                };
+                if decided {
+                    Ok(left)
+                } else {
+                    right.eval(interpreter)
+                }
+            }
+            // Only the chosen branch is evaluated.
+            Ternary {
+                cond,
+                then_expr,
+                else_expr,
+                ..
+            } => {
+                if cond.eval(interpreter)?.is_truthy() {
+                    then_expr.eval(interpreter)
+                } else {
+                    else_expr.eval(interpreter)
+                }
+            }
+            // Each operand is evaluated once, and as for `and`, evaluation stops at the
+            // first comparison that's false.
+            Chain { first, rest } => {
+                let mut left = first.eval(interpreter)?;
+                for (op, place, operand) in rest {
+                    let right = operand.eval(interpreter)?;
+                    let result = apply_binary(op, left, right.clone()).map_err(at(place))?;
+                    if !result.is_truthy() {
+                        return Ok(result);
+                    }
+                    left = right;
+                }
+                Ok(Value::Bool(true))
+            }
         }
     }
 }
 
-fn apply_unary(op: &ast::UnaryOp, value: Value) -> Result<Value> {
-    match op {
-        ast::UnaryOp::Not => Ok(value.not()),
-        _other => Err(anyhow!("{:?} not implemented", op)),
+/// Apply a unary operator to an evaluated operand, returning an error message if the
+/// operand has the wrong type.
+fn apply_unary(op: &ast::UnaryOp, value: Value) -> Result<Value, String> {
+    match (op, value) {
+        (ast::UnaryOp::Not, value) => Ok(value.not()),
+        (ast::UnaryOp::Negative, Value::Number(n)) => Ok(Value::Number(-n)),
+        (ast::UnaryOp::Negative, value) => Err(format!(
+            "operand of '{}' must be a number, not {}",
+            op,
+            value.type_name()
+        )),
+    }
+}
+
+/// Apply a binary operator to two evaluated operands.
+///
+/// Values of any type can be compared for equality, and values of different types are never
+/// equal. Only numbers can be compared for ordering.
+///
+/// Division by zero follows IEEE floating point, giving an infinity or NaN, rather than
+/// being an error.
+fn apply_binary(op: &ast::BinaryOp, left: Value, right: Value) -> Result<Value, String> {
+    use ast::BinaryOp::*;
+    use Value::{Bool, Number};
+    match (op, left, right) {
+        (Comma, _, right) => Ok(right),
+        (Plus, Number(a), Number(b)) => Ok(Number(a + b)),
+        (Minus, Number(a), Number(b)) => Ok(Number(a - b)),
+        (Multiply, Number(a), Number(b)) => Ok(Number(a * b)),
+        (Divide, Number(a), Number(b)) => Ok(Number(a / b)),
+        // As for f64, the result has the sign of `a`, and `a % 0` is NaN.
+        (Modulo, Number(a), Number(b)) => Ok(Number(a % b)),
+        (Power, Number(a), Number(b)) => Ok(Number(a.powf(b))),
+        (LessThan, Number(a), Number(b)) => Ok(Bool(a < b)),
+        (LessEqual, Number(a), Number(b)) => Ok(Bool(a <= b)),
+        (GreaterThan, Number(a), Number(b)) => Ok(Bool(a > b)),
+        (GreaterEqual, Number(a), Number(b)) => Ok(Bool(a >= b)),
+        (EqualEqual, left, right) => Ok(Bool(left == right)),
+        (NotEqual, left, right) => Ok(Bool(left != right)),
+        (Plus, Value::String(a), Value::String(b)) => Ok(Value::String(a + &b)),
+        (Plus, left, right) => Err(format!(
+            "operands of '+' must be two numbers or two strings, not {} and {}",
+            left.type_name(),
+            right.type_name()
+        )),
+        (
+            Minus | Multiply | Divide | Modulo | Power | LessThan | LessEqual | GreaterThan
+            | GreaterEqual,
+            left,
+            right,
+        ) => Err(format!(
+            "operands of '{}' must be numbers, not {} and {}",
+            op,
+            left.type_name(),
+            right.type_name()
+        )),
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::Interpreter;
-    use crate::value::Value;
+    use std::cell::{Cell, RefCell};
+    use std::io::Write;
+    use std::rc::Rc;
+
+    use anyhow::Result;
+
+    use super::{
+        CompileError, CompileErrors, Interpreter, LoxCallable, RuntimeError, MAX_EVAL_DEPTH,
+    };
+    use crate::parse::ParseOptions;
+    use crate::place::Place;
+    use crate::value::{NativeFn, Value};
 
     #[test]
     fn eval_literal_integer() {
         assert_eq!(
             Interpreter::new().eval("1234").unwrap(),
-            Value::Number(1234.0)
+            Some(Value::Number(1234.0))
+        );
+    }
+
+    #[test]
+    fn eval_extreme_exponents() {
+        let mut interpreter = Interpreter::new();
+        for (source, number, display) in [
+            ("1e300", 1e300, "1e300"),
+            ("1e-300", 1e-300, "1e-300"),
+            ("1e400", f64::INFINITY, "inf"),
+        ] {
+            let value = interpreter.eval(source).unwrap().unwrap();
+            assert_eq!(value, Value::Number(number));
+            assert_eq!(value.to_string(), display);
+        }
+    }
+
+    #[test]
+    fn eval_returns_last_value() {
+        assert_eq!(
+            Interpreter::new().eval("1; \"two\"; true").unwrap(),
+            Some(Value::Bool(true))
+        );
+        assert_eq!(Interpreter::new().eval("").unwrap(), None);
+        assert_eq!(Interpreter::new().eval("1; print 2;").unwrap(), None);
+    }
+
+    #[test]
+    fn eval_lex_errors() {
+        let err = Interpreter::new().eval("1 @ 2 #").unwrap_err();
+        assert_eq!(err.to_string(), "2 lex error(s)");
+        let errors = err.downcast::<CompileErrors>().unwrap();
+        assert_eq!(
+            errors.0.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            [
+                "[line 1 column 3] Error: unexpected character '@'. [E001]",
+                "[line 1 column 7] Error: unexpected character '#'. [E001]",
+            ]
+        );
+    }
+
+    #[test]
+    fn eval_parse_errors() {
+        let err = Interpreter::new().eval("+1; 2; *3;").unwrap_err();
+        assert_eq!(err.to_string(), "2 parse error(s)");
+        let errors = err.downcast::<CompileErrors>().unwrap();
+        assert_eq!(
+            errors.0.iter().map(CompileError::place).collect::<Vec<_>>(),
+            [Some(Place::new(1, 1, 0)), Some(Place::new(1, 8, 7))]
+        );
+    }
+
+    #[test]
+    fn check_returns_resolve_errors() {
+        let err = Interpreter::new()
+            .check("return 1;")
+            .unwrap_err()
+            .downcast::<CompileErrors>()
+            .unwrap();
+        assert_eq!(err.to_string(), "1 resolve error(s)");
+        assert!(matches!(err.0[..], [CompileError::Resolve(_)]));
+    }
+
+    #[test]
+    fn eval_reports_compile_errors_in_evaluated_source() {
+        assert_eq!(
+            eval("eval(\"1 + @\")").unwrap_err().to_string(),
+            "[line 1 column 5] Error: eval: unexpected character '@' at line 1 column 5."
+        );
+        assert_eq!(
+            eval("eval(\"1 +\")").unwrap_err().to_string(),
+            "[line 1 column 5] Error: eval: expected expression, found end of input."
+        );
+    }
+
+    /// Evaluate source ending in an expression, and return its value.
+    fn eval(source: &str) -> Result<Value> {
+        Interpreter::new()
+            .eval(source)
+            .map(|value| value.expect("source ends with an expression"))
+    }
+
+    #[test]
+    fn eval_arithmetic() {
+        assert_eq!(eval("2 + 3 * 4").unwrap(), Value::Number(14.0));
+        assert_eq!(eval("10 / 4").unwrap(), Value::Number(2.5));
+        assert_eq!(eval("(1 - 2) - 3").unwrap(), Value::Number(-4.0));
+    }
+
+    #[test]
+    fn eval_divide_by_zero_is_infinite() {
+        assert_eq!(eval("1 / 0").unwrap(), Value::Number(f64::INFINITY));
+    }
+
+    #[test]
+    fn eval_modulo() {
+        assert_eq!(eval("7 % 3").unwrap(), Value::Number(1.0));
+        assert_eq!(eval("-7 % 3").unwrap(), Value::Number(-1.0));
+        assert_eq!(eval("7.5 % 2").unwrap(), Value::Number(1.5));
+        assert_eq!(eval("1 + 7 % 3 * 2").unwrap(), Value::Number(3.0));
+    }
+
+    #[test]
+    fn eval_modulo_by_zero_is_nan() {
+        match eval("1 % 0").unwrap() {
+            Value::Number(n) => assert!(n.is_nan()),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn eval_modulo_type_mismatch() {
+        assert_eq!(
+            eval("\"7\" % 2").unwrap_err().to_string(),
+            "[line 1 column 5] Error: operands of '%' must be numbers, not string and number."
+        );
+    }
+
+    #[test]
+    fn eval_power() {
+        assert_eq!(eval("2 ** 10").unwrap(), Value::Number(1024.0));
+        assert_eq!(eval("2 ** 3 ** 2").unwrap(), Value::Number(512.0));
+        assert_eq!(eval("-2 ** 2").unwrap(), Value::Number(-4.0));
+        assert_eq!(eval("2 ** -1").unwrap(), Value::Number(0.5));
+        assert_eq!(eval("9 ** 0.5").unwrap(), Value::Number(3.0));
+        assert_eq!(
+            eval("2 ** nil").unwrap_err().to_string(),
+            "[line 1 column 3] Error: operands of '**' must be numbers, not number and nil."
         );
     }
+
+    #[test]
+    fn eval_string_concatenation() {
+        assert_eq!(eval("\"mb\" + \"plox\"").unwrap(), Value::from("mbplox"));
+    }
+
+    #[test]
+    fn eval_comma_yields_right_operand() {
+        assert_eq!(eval("1, \"two\", 3").unwrap(), Value::Number(3.0));
+    }
+
+    #[test]
+    fn eval_arithmetic_type_mismatch() {
+        assert_eq!(
+            eval("true - 1").unwrap_err().to_string(),
+            "[line 1 column 6] Error: operands of '-' must be numbers, not bool and number."
+        );
+    }
+
+    #[test]
+    fn runtime_error_reports_operator_place() {
+        let err = eval("1 + \"x\"")
+            .unwrap_err()
+            .downcast::<RuntimeError>()
+            .unwrap();
+        assert_eq!(err.place, Place::new(1, 3, 2));
+        assert_eq!(
+            err.to_string(),
+            "[line 1 column 3] Error: operands of '+' must be two numbers or two strings, not number and string."
+        );
+    }
+
+    #[test]
+    fn eval_number_ordering() {
+        for (source, expected) in [
+            ("1 < 2", true),
+            ("2 < 2", false),
+            ("2 <= 2", true),
+            ("3 <= 2", false),
+            ("3 > 2", true),
+            ("2 > 2", false),
+            ("2 >= 2", true),
+            ("1 >= 2", false),
+        ] {
+            assert_eq!(eval(source).unwrap(), Value::Bool(expected), "{}", source);
+        }
+    }
+
+    #[test]
+    fn eval_ordering_type_mismatch() {
+        assert_eq!(
+            eval("\"a\" < \"b\"").unwrap_err().to_string(),
+            "[line 1 column 5] Error: operands of '<' must be numbers, not string and string."
+        );
+    }
+
+    #[test]
+    fn eval_equality() {
+        for (source, expected) in [
+            ("nil == nil", true),
+            ("true == true", true),
+            ("true != false", true),
+            ("1 == 1.0", true),
+            ("1 != 2", true),
+            ("\"a\" == \"a\"", true),
+            ("1 == \"1\"", false),
+            ("nil == false", false),
+            ("0 == false", false),
+            ("nil != nil", false),
+            ("1 + 1 == 2 == true", true),
+            ("0 == -0", true),
+            ("0/0 == 0/0", false),
+            ("0/0 != 0/0", true),
+        ] {
+            assert_eq!(eval(source).unwrap(), Value::Bool(expected), "{}", source);
+        }
+    }
+
+    #[test]
+    fn eval_negation() {
+        assert_eq!(eval("-5").unwrap(), Value::Number(-5.0));
+        assert_eq!(eval("--5").unwrap(), Value::Number(5.0));
+        assert_eq!(eval("1 - -2").unwrap(), Value::Number(3.0));
+        assert_eq!(
+            eval("-\"x\"").unwrap_err().to_string(),
+            "[line 1 column 1] Error: operand of '-' must be a number, not string."
+        );
+    }
+
+    #[test]
+    fn global_variables() {
+        assert_eq!(eval("var x = 3; x").unwrap(), Value::Number(3.0));
+        assert_eq!(
+            eval("var x = 1; var y = x + 1; y").unwrap(),
+            Value::Number(2.0)
+        );
+        assert_eq!(
+            eval("var x = 1; var x = \"again\"; x").unwrap(),
+            Value::from("again")
+        );
+    }
+
+    #[test]
+    fn uninitialized_variable_is_nil() {
+        assert_eq!(eval("var x; x").unwrap(), Value::Nil);
+    }
+
+    #[test]
+    fn globals_persist_between_evals() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("var greeting = \"hello\";").unwrap();
+        assert_eq!(
+            interpreter.eval("greeting").unwrap(),
+            Some(Value::from("hello"))
+        );
+    }
+
+    #[test]
+    fn hooks_see_calls_and_returns() {
+        let calls = RefCell::new(Vec::new());
+        let returns = RefCell::new(Vec::new());
+        let mut interpreter = Interpreter::new();
+        interpreter.set_on_call(Box::new(|name, args| {
+            calls.borrow_mut().push((name.to_owned(), args.to_vec()))
+        }));
+        interpreter.set_on_return(Box::new(|name, value| {
+            returns.borrow_mut().push((name.to_owned(), value.to_vec()))
+        }));
+        interpreter
+            .eval("fun add(a, b) { return a + b; } add(1, 2); add(\"x\", \"y\");")
+            .unwrap();
+        drop(interpreter);
+        assert_eq!(
+            calls.into_inner(),
+            [
+                (
+                    "add".to_owned(),
+                    vec![Value::Number(1.0), Value::Number(2.0)]
+                ),
+                ("add".to_owned(), vec![Value::from("x"), Value::from("y")]),
+            ]
+        );
+        assert_eq!(
+            returns.into_inner(),
+            [
+                ("add".to_owned(), vec![Value::Number(3.0)]),
+                ("add".to_owned(), vec![Value::from("xy")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn reset_forgets_globals() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("var a = 1; const b = 2;").unwrap();
+        interpreter.reset();
+        assert_eq!(
+            interpreter.eval("a").unwrap_err().to_string(),
+            "[line 1 column 1] Error: undefined variable 'a'."
+        );
+        // Assigning to b is no longer rejected as assigning to a constant: it's just
+        // undefined.
+        assert_eq!(
+            interpreter.eval("b = 4;").unwrap_err().to_string(),
+            "[line 1 column 1] Error: undefined variable 'b'."
+        );
+        assert!(matches!(
+            interpreter.eval("clock").unwrap(),
+            Some(Value::NativeFn(_))
+        ));
+    }
+
+    #[test]
+    fn constants_persist_between_evals() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("const greeting = \"hello\";").unwrap();
+        assert!(interpreter.eval("greeting = \"goodbye\";").is_err());
+        assert_eq!(
+            interpreter.eval("greeting").unwrap(),
+            Some(Value::from("hello"))
+        );
+    }
+
+    #[test]
+    fn undefined_variable_is_error() {
+        let err = eval("1;\nprint 2 + nope;")
+            .unwrap_err()
+            .downcast::<RuntimeError>()
+            .unwrap();
+        assert_eq!(err.place, Place::new(2, 11, 13));
+        assert_eq!(
+            err.to_string(),
+            "[line 2 column 11] Error: undefined variable 'nope'."
+        );
+    }
+
+    #[test]
+    fn assignment_updates_variable_and_yields_value() {
+        assert_eq!(eval("var a = 1; a = 2; a").unwrap(), Value::Number(2.0));
+        assert_eq!(eval("var a = 1; a = a + 1").unwrap(), Value::Number(2.0));
+        assert_eq!(
+            eval("var a; var b; a = b = \"x\"; a + b").unwrap(),
+            Value::from("xx")
+        );
+    }
+
+    #[test]
+    fn assignment_to_undefined_variable_is_error() {
+        assert_eq!(
+            eval("nope = 1").unwrap_err().to_string(),
+            "[line 1 column 1] Error: undefined variable 'nope'."
+        );
+    }
+
+    #[test]
+    fn block_variable_shadows_outer_until_block_ends() {
+        assert_eq!(
+            eval("var a = \"outer\"; var seen; { var a = \"inner\"; seen = a; } seen + \" \" + a")
+                .unwrap(),
+            Value::from("inner outer")
+        );
+    }
+
+    #[test]
+    fn assignment_in_block_updates_outer_variable() {
+        assert_eq!(
+            eval("var a = 1; { { a = a + 1; } } a").unwrap(),
+            Value::Number(2.0)
+        );
+    }
+
+    #[test]
+    fn block_scope_is_discarded_after_error() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval("var a = 1;").unwrap();
+        interpreter
+            .eval("{ var a = 2; var b = 3; -nil; }")
+            .unwrap_err();
+        assert_eq!(interpreter.eval("a").unwrap(), Some(Value::Number(1.0)));
+        assert_eq!(
+            interpreter.eval("b").unwrap_err().to_string(),
+            "[line 1 column 1] Error: undefined variable 'b'."
+        );
+    }
+
+    #[test]
+    fn while_loop_runs_until_condition_is_falsey() {
+        assert_eq!(
+            eval("var i = 0; var sum = 0; while (i < 5) { i = i + 1; sum = sum + i; } sum")
+                .unwrap(),
+            Value::Number(15.0)
+        );
+        assert_eq!(
+            eval("var ran = false; while (nil) ran = true; ran").unwrap(),
+            Value::Bool(false)
+        );
+    }
+
+    #[test]
+    fn break_leaves_loop_early() {
+        assert_eq!(
+            eval("var i = 0; while (true) { i = i + 1; if (i == 3) break; } i").unwrap(),
+            Value::Number(3.0)
+        );
+        assert_eq!(
+            eval(
+                "var n = 0; for (var i = 0; i < 10; i = i + 1) { if (i == 4) break; n = n + 1; } n"
+            )
+            .unwrap(),
+            Value::Number(4.0)
+        );
+    }
+
+    #[test]
+    fn continue_in_for_still_runs_increment() {
+        assert_eq!(
+            eval("var sum = 0; for (var i = 0; i < 6; i = i + 1) { if (i == 2) continue; if (i == 4) continue; sum = sum + i; } sum")
+                .unwrap(),
+            Value::Number(9.0)
+        );
+    }
+
+    #[test]
+    fn break_only_leaves_innermost_loop() {
+        assert_eq!(
+            eval("var n = 0; for (var i = 0; i < 3; i = i + 1) { while (true) { n = n + 1; break; } } n")
+                .unwrap(),
+            Value::Number(3.0)
+        );
+    }
+
+    #[test]
+    fn call_native_function() {
+        match eval("clock()").unwrap() {
+            Value::Number(secs) => assert!(secs > 1.6e9, "{}", secs),
+            other => panic!("clock returned {:?}", other),
+        }
+    }
+
+    #[test]
+    fn call_with_wrong_number_of_arguments_is_error() {
+        assert_eq!(
+            eval("clock(1, 2)").unwrap_err().to_string(),
+            "[line 1 column 6] Error: clock expected 0 arguments but got 2."
+        );
+    }
+
+    #[test]
+    fn call_non_function_is_error() {
+        assert_eq!(
+            eval("var x = 1;\nx()").unwrap_err().to_string(),
+            "[line 2 column 2] Error: can only call functions and classes."
+        );
+    }
+
+    #[test]
+    fn len_counts_chars() {
+        assert_eq!(eval("len(\"h\u{e9}llo\")").unwrap(), Value::Number(5.0));
+        assert_eq!(eval("len(\"\")").unwrap(), Value::Number(0.0));
+        assert_eq!(
+            eval("len(12)").unwrap_err().to_string(),
+            "[line 1 column 4] Error: len: expected string, found number."
+        );
+    }
+
+    #[test]
+    fn substr_counts_chars() {
+        for (source, expected) in [
+            ("substr(\"hello\", 1, 3)", "ell"),
+            ("substr(\"hello\", 0, 5)", "hello"),
+            ("substr(\"hello\", 5, 0)", ""),
+            ("substr(\"h\u{e9}llo\", 1, 2)", "\u{e9}l"),
+        ] {
+            assert_eq!(eval(source).unwrap(), Value::from(expected), "{}", source);
+        }
+    }
+
+    #[test]
+    fn substr_errors() {
+        for (source, message) in [
+            (
+                "substr(\"hello\", 3, 3)",
+                "substr: start 3 and length 3 are out of range for a string of length 5",
+            ),
+            (
+                "substr(\"hello\", -1, 1)",
+                "substr: start must be a non-negative whole number, not -1",
+            ),
+            (
+                "substr(\"hello\", 0, 1.5)",
+                "substr: length must be a non-negative whole number, not 1.5",
+            ),
+            ("substr(nil, 0, 1)", "substr: expected string, found nil"),
+            (
+                "substr(\"hello\", \"0\", 1)",
+                "substr: expected number, found string",
+            ),
+        ] {
+            assert_eq!(
+                eval(source).unwrap_err().to_string(),
+                format!("[line 1 column 7] Error: {}.", message),
+                "{}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn repeat_concatenates_copies() {
+        assert_eq!(eval("repeat(\"ab\", 3)").unwrap(), Value::from("ababab"));
+        assert_eq!(eval("repeat(\"x\", 0)").unwrap(), Value::from(""));
+        assert_eq!(eval("repeat(\"\", 5)").unwrap(), Value::from(""));
+    }
+
+    #[test]
+    fn repeat_errors() {
+        for (source, message) in [
+            (
+                "repeat(\"x\", -1)",
+                "repeat: count must be a non-negative whole number, not -1",
+            ),
+            (
+                "repeat(\"x\", 2.5)",
+                "repeat: count must be a non-negative whole number, not 2.5",
+            ),
+            ("repeat(1, 2)", "repeat: expected string, found number"),
+            (
+                "repeat(\"xy\", 1e19)",
+                "repeat: 10000000000000000000 copies would be too long",
+            ),
+        ] {
+            assert_eq!(
+                eval(source).unwrap_err().to_string(),
+                format!("[line 1 column 7] Error: {}.", message),
+                "{}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn trim_removes_surrounding_whitespace() {
+        assert_eq!(eval("trim(\"  a b \t\")").unwrap(), Value::from("a b"));
+        assert_eq!(eval("trim(\"ab\")").unwrap(), Value::from("ab"));
+    }
+
+    #[test]
+    fn string_predicates() {
+        for (source, expected) in [
+            ("starts_with(\"hello\", \"he\")", true),
+            ("starts_with(\"hello\", \"lo\")", false),
+            ("ends_with(\"hello\", \"lo\")", true),
+            ("ends_with(\"hello\", \"he\")", false),
+            ("contains(\"hello\", \"ell\")", true),
+            ("contains(\"hello\", \"elk\")", false),
+            ("contains(\"hello\", \"\")", true),
+        ] {
+            assert_eq!(eval(source).unwrap(), Value::Bool(expected), "{}", source);
+        }
+    }
+
+    #[test]
+    fn string_predicates_need_strings() {
+        assert_eq!(
+            eval("contains(\"a1\", 1)").unwrap_err().to_string(),
+            "[line 1 column 9] Error: contains: expected string, found number."
+        );
+        assert_eq!(
+            eval("trim(nil)").unwrap_err().to_string(),
+            "[line 1 column 5] Error: trim: expected string, found nil."
+        );
+    }
+
+    #[test]
+    fn eval_returns_last_expression() {
+        assert_eq!(eval("eval(\"1 + 2\")").unwrap(), Value::Number(3.0));
+        assert_eq!(eval("eval(\"var a = 1;\")").unwrap(), Value::Nil);
+    }
+
+    #[test]
+    fn eval_defines_globals() {
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .eval("fun f() { var local = 1; eval(\"var made = local;\"); }")
+            .unwrap();
+        assert_eq!(
+            interpreter.eval("f();").unwrap_err().to_string(),
+            "[line 1 column 30] Error: eval: undefined variable 'local' at line 1 column 12."
+        );
+        interpreter.eval("eval(\"var made = 2;\");").unwrap();
+        assert_eq!(interpreter.eval("made").unwrap(), Some(Value::Number(2.0)));
+    }
+
+    #[test]
+    fn eval_nesting_is_limited() {
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .eval("var depth = 0; var source = \"depth = depth + 1; eval(source);\";")
+            .unwrap();
+        let message = interpreter.eval("eval(source);").unwrap_err().to_string();
+        assert_eq!(
+            message,
+            "[line 1 column 5] Error: eval: nested more than 100 deep."
+        );
+        assert_eq!(
+            interpreter.eval("depth").unwrap(),
+            Some(Value::Number(MAX_EVAL_DEPTH as f64))
+        );
+        // The depth is back to zero after the error.
+        assert_eq!(
+            interpreter.eval("eval(\"depth\")").unwrap(),
+            Some(Value::Number(MAX_EVAL_DEPTH as f64))
+        );
+    }
+
+    #[test]
+    fn str_and_num_round_trip() {
+        assert_eq!(eval("str(123)").unwrap(), Value::from("123"));
+        assert_eq!(eval("str(nil)").unwrap(), Value::from("nil"));
+        assert_eq!(eval("str(\"hi\")").unwrap(), Value::from("hi"));
+        assert_eq!(eval("num(\"4.5\")").unwrap(), Value::Number(4.5));
+        assert_eq!(eval("num(\" -2 \")").unwrap(), Value::Number(-2.0));
+        assert_eq!(eval("num(str(0.125)) == 0.125").unwrap(), Value::Bool(true));
+        assert_eq!(eval("str(num(\"17\"))").unwrap(), Value::from("17"));
+    }
+
+    #[test]
+    fn num_of_non_number_is_nil() {
+        assert_eq!(eval("num(\"four\")").unwrap(), Value::Nil);
+        assert_eq!(eval("num(\"\")").unwrap(), Value::Nil);
+        assert_eq!(
+            eval("num(4)").unwrap_err().to_string(),
+            "[line 1 column 4] Error: num: expected string, found number."
+        );
+    }
+
+    #[test]
+    fn clock_is_a_native_returning_seconds() {
+        let clock = match eval("clock").unwrap() {
+            Value::NativeFn(clock) => clock,
+            other => panic!("clock is {:?}", other),
+        };
+        assert_eq!(Value::NativeFn(clock.clone()).to_string(), "<native fn>");
+        assert_eq!(clock.name(), "clock");
+        assert_eq!(clock.arity(), 0);
+        match clock.call(&mut Interpreter::new(), vec![], Place::new(1, 1, 0)) {
+            Ok(Value::Number(secs)) => assert!(secs > 1.6e9, "{}", secs),
+            other => panic!("clock returned {:?}", other),
+        }
+    }
+
+    #[test]
+    fn call_user_function_through_callable() {
+        let mut interpreter = Interpreter::new();
+        let add = match interpreter.eval("fun add(a, b) { return a + b; } add") {
+            Ok(Some(Value::Function(add))) => add,
+            other => panic!("add is {:?}", other),
+        };
+        assert_eq!(add.name(), "add");
+        assert_eq!(add.arity(), 2);
+        assert_eq!(
+            add.call(
+                &mut interpreter,
+                vec![Value::Number(2.0), Value::Number(3.0)],
+                Place::new(1, 1, 0)
+            ),
+            Ok(Value::Number(5.0))
+        );
+    }
+
+    #[test]
+    fn call_user_function() {
+        assert_eq!(
+            eval("var total = 0; fun add(a, b) { total = a + b; } add(2, 3); total").unwrap(),
+            Value::Number(5.0)
+        );
+        assert_eq!(eval("fun f() {} f").unwrap().to_string(), "<fn f>");
+        assert_eq!(eval("fun f() {} f()").unwrap(), Value::Nil);
+    }
+
+    #[test]
+    fn return_value_from_function() {
+        assert_eq!(
+            eval("fun add(a, b) { return a + b; } add(2, 3)").unwrap(),
+            Value::Number(5.0)
+        );
+    }
+
+    #[test]
+    fn early_return_skips_rest_of_function() {
+        assert_eq!(
+            eval("var after = false; fun f() { return 1; after = true; } f(); after").unwrap(),
+            Value::Bool(false)
+        );
+    }
+
+    #[test]
+    fn bare_return_yields_nil() {
+        assert_eq!(eval("fun f() { return; } f()").unwrap(), Value::Nil);
+    }
+
+    #[test]
+    fn return_from_nested_blocks_and_loops() {
+        assert_eq!(
+            eval(
+                "fun find() { for (var i = 0; ; i = i + 1) { { if (i == 3) return i; } } } find()"
+            )
+            .unwrap(),
+            Value::Number(3.0)
+        );
+    }
+
+    #[test]
+    fn recursive_function() {
+        assert_eq!(
+            eval("fun fib(n) { if (n < 2) return n; return fib(n - 2) + fib(n - 1); } fib(10)")
+                .unwrap(),
+            Value::Number(55.0)
+        );
+    }
+
+    #[test]
+    fn function_parameters_are_local() {
+        assert_eq!(
+            eval("var a = \"global\"; fun f(a) { a = 2; } f(1); a").unwrap(),
+            Value::from("global")
+        );
+    }
+
+    #[test]
+    fn call_user_function_with_wrong_arity_is_error() {
+        assert_eq!(
+            eval("fun add(a, b) {}\nadd(1)").unwrap_err().to_string(),
+            "[line 2 column 4] Error: add expected 2 arguments but got 1."
+        );
+    }
+
+    #[test]
+    fn anonymous_function_closes_over_scope() {
+        assert_eq!(
+            eval("fun adder(n) { return fun (x) { return x + n; }; } adder(3)(4)").unwrap(),
+            Value::Number(7.0)
+        );
+        assert_eq!(eval("fun () {}").unwrap().to_string(), "<fn>");
+    }
+
+    #[test]
+    fn call_anonymous_function_with_wrong_arity_is_error() {
+        assert_eq!(
+            eval("var f = fun (a) {};\nf()").unwrap_err().to_string(),
+            "[line 2 column 2] Error: anonymous function expected 1 arguments but got 0."
+        );
+    }
+
+    #[test]
+    fn ternary_evaluates_chosen_branch() {
+        assert_eq!(eval("true ? 1 : 2").unwrap(), Value::Number(1.0));
+        assert_eq!(eval("nil ? 1 : 2").unwrap(), Value::Number(2.0));
+        assert_eq!(eval("0 ? \"yes\" : \"no\"").unwrap(), Value::from("yes"));
+    }
+
+    #[test]
+    fn logical_operators_yield_deciding_operand() {
+        for (source, expected) in [
+            ("nil or 5", Value::Number(5.0)),
+            ("0 or 5", Value::Number(0.0)),
+            ("false or nil", Value::Nil),
+            ("\"a\" and \"b\"", Value::from("b")),
+            ("nil and 5", Value::Nil),
+            ("false and nil", Value::Bool(false)),
+            ("1 and 2 or 3", Value::Number(2.0)),
+            ("nil and 2 or 3", Value::Number(3.0)),
+        ] {
+            assert_eq!(eval(source).unwrap(), expected, "{}", source);
+        }
+    }
+
+    thread_local! {
+        static RECORDED: Cell<usize> = const { Cell::new(0) };
+    }
+
+    /// A native function that counts its calls, and returns its argument.
+    fn record(_interpreter: &mut Interpreter<'_>, args: &[Value]) -> Result<Value, String> {
+        RECORDED.with(|calls| calls.set(calls.get() + 1));
+        Ok(args[0].clone())
+    }
+
+    /// Make an interpreter with the `record` native function defined.
+    fn recording_interpreter() -> Interpreter<'static> {
+        let interpreter = Interpreter::new();
+        interpreter.globals.borrow_mut().define(
+            "record",
+            Value::NativeFn(Rc::new(NativeFn {
+                name: "record",
+                arity: 1,
+                fun: record,
+            })),
+        );
+        interpreter
+    }
+
+    #[test]
+    fn logical_operators_short_circuit() {
+        let mut interpreter = recording_interpreter();
+        for (source, expected, calls) in [
+            ("false and record(1)", Value::Bool(false), 0),
+            ("true or record(2)", Value::Bool(true), 0),
+            ("true and record(3)", Value::Number(3.0), 1),
+            ("nil or record(4)", Value::Number(4.0), 1),
+            ("record(nil) and record(5)", Value::Nil, 1),
+        ] {
+            RECORDED.with(|recorded| recorded.set(0));
+            assert_eq!(
+                interpreter.eval(source).unwrap(),
+                Some(expected),
+                "{}",
+                source
+            );
+            assert_eq!(RECORDED.with(Cell::get), calls, "{}", source);
+        }
+    }
+
+    #[test]
+    fn math_chains_compare_neighbors() {
+        let mut interpreter = recording_interpreter();
+        interpreter.set_parse_options(ParseOptions { math_chains: true });
+        for (source, expected, calls) in [
+            ("1 < 2 < 3", true, 0),
+            ("3 < 2 < 1", false, 0),
+            ("1 < 3 > 2", true, 0),
+            ("1 <= 1 < 1", false, 0),
+            // The middle operand is evaluated only once.
+            ("1 < record(2) < 3", true, 1),
+            // Evaluation stops at the first false comparison.
+            ("2 < 1 < record(3)", false, 0),
+        ] {
+            RECORDED.with(|recorded| recorded.set(0));
+            assert_eq!(
+                interpreter.eval(source).unwrap(),
+                Some(Value::Bool(expected)),
+                "{}",
+                source
+            );
+            assert_eq!(RECORDED.with(Cell::get), calls, "{}", source);
+        }
+    }
+
+    #[test]
+    fn chained_comparisons_without_math_chains() {
+        assert_eq!(
+            eval("1 < 2 < 3").unwrap_err().to_string(),
+            "[line 1 column 7] Error: operands of '<' must be numbers, not bool and number."
+        );
+    }
+
+    #[test]
+    fn nested_ternary_is_right_associative() {
+        for (source, expected) in [
+            ("true ? 1 : true ? 2 : 3", 1.0),
+            ("false ? 1 : true ? 2 : 3", 2.0),
+            ("false ? 1 : false ? 2 : 3", 3.0),
+        ] {
+            assert_eq!(eval(source).unwrap(), Value::Number(expected), "{}", source);
+        }
+    }
+
+    #[test]
+    fn ternary_branch_not_taken_is_not_evaluated() {
+        let mut interpreter = recording_interpreter();
+        for (source, expected) in [
+            ("true ? record(1) : record(2)", 1.0),
+            ("false ? record(1) : record(2)", 2.0),
+            ("false ? record(1) : true ? record(2) : record(3)", 2.0),
+        ] {
+            RECORDED.with(|recorded| recorded.set(0));
+            assert_eq!(
+                interpreter.eval(source).unwrap(),
+                Some(Value::Number(expected)),
+                "{}",
+                source
+            );
+            assert_eq!(RECORDED.with(Cell::get), 1, "{}", source);
+        }
+    }
+
+    #[test]
+    fn eval_not_follows_truthiness() {
+        for (source, expected) in [
+            ("!0", false),
+            ("!\"\"", false),
+            ("!\"x\"", false),
+            ("!nil", true),
+            ("!false", true),
+            ("!true", false),
+            ("!!nil", false),
+        ] {
+            assert_eq!(eval(source).unwrap(), Value::Bool(expected), "{}", source);
+        }
+    }
+
+    #[test]
+    fn set_and_get_field() {
+        assert_eq!(
+            eval("class P {} var p = P(); p.x = 3; p.x + 1").unwrap(),
+            Value::Number(4.0)
+        );
+    }
+
+    #[test]
+    fn set_yields_value() {
+        assert_eq!(
+            eval("class P {} var p = P(); p.x = p.y = \"v\"").unwrap(),
+            Value::from("v")
+        );
+    }
+
+    #[test]
+    fn chained_field_access() {
+        assert_eq!(
+            eval("class P {} var a = P(); a.b = P(); a.b.c = 7; a.b.c").unwrap(),
+            Value::Number(7.0)
+        );
+    }
+
+    #[test]
+    fn instances_are_shared() {
+        assert_eq!(
+            eval("class P {} var a = P(); var b = a; b.x = 1; a.x").unwrap(),
+            Value::Number(1.0)
+        );
+    }
+
+    #[test]
+    fn undefined_property_is_error() {
+        assert_eq!(
+            eval("class P {}\nP().nope").unwrap_err().to_string(),
+            "[line 2 column 5] Error: undefined property 'nope'."
+        );
+    }
+
+    #[test]
+    fn field_of_non_instance_is_error() {
+        assert_eq!(
+            eval("var a = 1;\na.x").unwrap_err().to_string(),
+            "[line 2 column 3] Error: only instances have fields."
+        );
+        assert_eq!(
+            eval("\"s\".x = 1").unwrap_err().to_string(),
+            "[line 1 column 5] Error: only instances have fields."
+        );
+    }
+
+    #[test]
+    fn method_sees_this() {
+        assert_eq!(
+            eval("class P { name() { return this.n; } } var p = P(); p.n = \"pat\"; p.name()")
+                .unwrap(),
+            Value::from("pat")
+        );
+    }
+
+    #[test]
+    fn bound_method_remembers_its_instance() {
+        assert_eq!(
+            eval(
+                "class C { get() { return this.v; } }
+                var a = C(); a.v = 1; var b = C(); b.v = 2;
+                var m = a.get; b.m = m; b.m()"
+            )
+            .unwrap(),
+            Value::Number(1.0)
+        );
+    }
+
+    #[test]
+    fn field_shadows_method() {
+        assert_eq!(
+            eval("class C { f() { return 1; } } var c = C(); c.f = 2; c.f").unwrap(),
+            Value::Number(2.0)
+        );
+    }
+
+    #[test]
+    fn class_init_sets_fields() {
+        assert_eq!(
+            eval("class P { init(x, y) { this.x = x; this.y = y; } } var p = P(3, 4); p.x * p.y")
+                .unwrap(),
+            Value::Number(12.0)
+        );
+    }
+
+    #[test]
+    fn class_arity_comes_from_init() {
+        assert_eq!(
+            eval("class P { init(x) {} }\nP()").unwrap_err().to_string(),
+            "[line 2 column 2] Error: P expected 1 arguments but got 0."
+        );
+        assert_eq!(
+            eval("class Q {}\nQ(1)").unwrap_err().to_string(),
+            "[line 2 column 2] Error: Q expected 0 arguments but got 1."
+        );
+    }
+
+    #[test]
+    fn init_returns_this_even_after_bare_return() {
+        assert_eq!(
+            eval("class P { init() { this.a = 1; return; this.a = 2; } } P().a").unwrap(),
+            Value::Number(1.0)
+        );
+        assert_eq!(
+            eval("class P { init() { return; } } var p = P(); p.init() == p").unwrap(),
+            Value::Bool(true)
+        );
+    }
+
+    #[test]
+    fn superclass_must_be_a_class() {
+        assert_eq!(
+            eval("var A = 1;\nclass B < A {}").unwrap_err().to_string(),
+            "[line 2 column 11] Error: superclass must be a class."
+        );
+    }
+
+    #[test]
+    fn subclass_inherits_init() {
+        assert_eq!(
+            eval("class A { init(x) { this.x = x; } } class B < A {} B(5).x").unwrap(),
+            Value::Number(5.0)
+        );
+    }
+
+    #[test]
+    fn print_goes_to_output() {
+        let mut buf: Vec<u8> = Vec::new();
+        let mut interpreter = Interpreter::with_output(Box::new(&mut buf));
+        assert_eq!(interpreter.eval("print 1; print 2;").unwrap(), None);
+        drop(interpreter);
+        assert_eq!(buf, b"1\n2\n");
+    }
+
+    /// A writer that appends to a buffer which the test can still read while the
+    /// interpreter owns the writer.
+    struct SharedBuf(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn output_is_flushed_after_runtime_error() {
+        let buf = Rc::new(RefCell::new(Vec::new()));
+        let writer = std::io::BufWriter::new(SharedBuf(Rc::clone(&buf)));
+        let mut interpreter = Interpreter::with_output(Box::new(writer));
+        assert!(interpreter.eval("print \"before\"; -nil;").is_err());
+        // Checked while the interpreter is still alive, so that dropping the BufWriter
+        // can't be what flushed it.
+        assert_eq!(*buf.borrow(), b"before\n");
+        drop(interpreter);
+    }
+
+    /// A writer that always fails, like a closed pipe.
+    struct FailingWriter;
+
+    impl Write for FailingWriter {
+        fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::BrokenPipe,
+                "pipe closed",
+            ))
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn print_write_failure_is_runtime_error() {
+        let mut interpreter = Interpreter::with_output(Box::new(FailingWriter));
+        for (source, column) in [("1;\nprint 2;", 1), ("1;\n  print;", 3)] {
+            let err = interpreter
+                .eval(source)
+                .unwrap_err()
+                .downcast::<RuntimeError>()
+                .unwrap();
+            assert_eq!(
+                err.to_string(),
+                format!(
+                    "[line 2 column {}] Error: failed to write output: pipe closed.",
+                    column
+                )
+            );
+        }
+    }
 }
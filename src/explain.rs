@@ -0,0 +1,174 @@
+// Copyright 2021 Martin Pool
+
+//! Longer explanations of diagnostic codes, shown by `--explain`.
+
+/// Explanations for each diagnostic code.
+static EXPLANATIONS: &[(&str, &str)] = &[
+    (
+        "E001",
+        "An unexpected character was found in the source.
+
+Lox source may contain letters, digits, whitespace, and the punctuation used by
+the language's operators. Other characters, such as `@` or `|`, can only occur
+inside strings or comments.",
+    ),
+    (
+        "E002",
+        "A string was still open at the end of the source.
+
+Strings start and end with a double quote `\"`, and may span multiple lines.
+This error is reported at the place of the opening quote: check that the string
+is closed.",
+    ),
+    (
+        "E003",
+        "A block comment was still open at the end of the source.
+
+Block comments start with `/*` and end at the next `*/`. They do not nest, so
+the first `*/` closes the comment. This error is reported at the place of the
+opening `/*`.",
+    ),
+    (
+        "E004",
+        "A number was malformed, for example by running straight into letters, as in `123abc`.
+
+This is usually a typo. Separate the number from a following identifier with a
+space or an operator. An exponent needs at least one digit, as in `1e3`.
+
+Hex, octal and binary numbers, such as `0xff`, `0o17` and `0b1010`, need at least
+one digit of their radix, and may not contain digits outside it. Their digits
+may be separated by single underscores, as in `0xFF_FF`, but an underscore can't
+come first, last, or next to another underscore.",
+    ),
+    (
+        "E005",
+        "The parser found a token other than the one it expected.
+
+For example, `+1` is an error because `+` cannot start an expression. The
+error names what was expected and the token that was found in its place.",
+    ),
+    (
+        "E006",
+        "The source ended before a construct was complete.
+
+For example, an expression can't be empty. The error names what was expected
+when the source ended.",
+    ),
+    (
+        "E007",
+        "A construct was opened, but the token that should close it is missing.
+
+For example, in a conditional expression `cond ? a : b`, each `?` must be
+followed by a matching `:`. The error gives the place of the opening token.",
+    ),
+    (
+        "E008",
+        "The left side of an assignment is not something that can be assigned to.
+
+Only a variable can be assigned, as in `x = 1`. Something like `1 = 2` or
+`a + b = c` is an error, reported at the `=`.",
+    ),
+    (
+        "E009",
+        "A `break` or `continue` statement is not inside a loop.
+
+These statements leave or restart the innermost `while` or `for` loop, so
+they can only be used in the body of a loop.",
+    ),
+    (
+        "E010",
+        "A function call passes more than 255 arguments, or a function declares
+more than 255 parameters.
+
+This limit keeps calls simple to implement. Consider passing fewer values,
+or grouping them together.",
+    ),
+    (
+        "E011",
+        "A `return` statement is not inside a function.
+
+`return` leaves the function that is running, so it can only be used in the
+body of a function.",
+    ),
+    (
+        "E012",
+        "A local variable is read in its own initializer.
+
+In `{ var a = a; }`, the `a` on the right refers to the new variable that is
+being declared, which doesn't have a value yet. To use an outer variable of the
+same name, give the new variable a different name.",
+    ),
+    (
+        "E013",
+        "A local variable is declared twice in the same scope.
+
+Inside a block or function, each variable name can be declared only once. This
+is usually a mistake: to change the variable's value, assign to it instead of
+declaring it again. (Global variables can be redeclared, which is convenient in
+the REPL.)",
+    ),
+    (
+        "E014",
+        "`this` is used outside of a class.
+
+`this` refers to the instance that a method was called on, so it can only be
+used inside the methods of a class, including in functions declared inside
+those methods.",
+    ),
+    (
+        "E015",
+        "A class's `init` method returns a value.
+
+Calling a class runs its `init` method and always yields the new instance, so
+a value returned from `init` could never be seen. `init` can use `return;` with
+no value to finish early.",
+    ),
+    (
+        "E016",
+        "`super` is used outside of a subclass.
+
+`super.method` finds a method in the superclass of the class being declared,
+so it can only be used inside the methods of a class declared with a
+superclass, as in `class B < A { ... }`.",
+    ),
+    (
+        "E017",
+        "The input ended inside a block, function body, or class body.
+
+Every `{` needs a matching `}`. The error gives the place of the `{` that was
+never closed, which may be far before the end of the input.",
+    ),
+    (
+        "E018",
+        "A constant is assigned a new value.
+
+A variable declared with `const`, as in `const PI = 3.14;`, keeps the value
+it's declared with. Declare it with `var` if it needs to change.",
+    ),
+];
+
+/// Return the explanation for a diagnostic code, if there is one.
+pub fn explain(code: &str) -> Option<&'static str> {
+    EXPLANATIONS
+        .iter()
+        .find(|(c, _)| c.eq_ignore_ascii_case(code))
+        .map(|(_, explanation)| *explanation)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn explain_known_code() {
+        assert!(explain("E002")
+            .unwrap()
+            .starts_with("A string was still open"));
+        assert_eq!(explain("e002"), explain("E002"));
+    }
+
+    #[test]
+    fn explain_unknown_code() {
+        assert_eq!(explain("E999"), None);
+    }
+}
@@ -6,7 +6,7 @@
 
 use std::fmt;
 
-use crate::place::Place;
+use crate::place::{Place, DEFAULT_TAB_WIDTH};
 use crate::scan::Scan;
 
 /// A specific type of lexical tokens, including the embedded value of literals, and the identifier
@@ -16,10 +16,14 @@ pub enum Tok {
     Plus,
     Minus,
     Star,
+    StarStar,
     Slash,
+    Percent,
     Comma,
     Dot,
     Semicolon,
+    Question,
+    Colon,
 
     LeftParen,
     RightParen,
@@ -42,9 +46,19 @@ pub enum Tok {
     Number(f64),
     Identifier(String),
 
+    /// The text of a `//` comment, after the slashes and up to the end of the line. Only
+    /// produced if [LexOptions::keep_comments] is set.
+    LineComment(String),
+    /// The text of a `/* */` comment, between the delimiters. Only produced if
+    /// [LexOptions::keep_comments] is set.
+    BlockComment(String),
+
     // keywords
     And,
+    Break,
     Class,
+    Const,
+    Continue,
     Else,
     Fun,
     For,
@@ -65,6 +79,8 @@ pub struct Token {
     pub tok: Tok,
     /// Place where this token starts.
     pub place: Place,
+    /// Place just after the last character of this token.
+    pub end: Place,
     /// Literal content of the lexeme.
     // TODO: Is the lexeme ever really needed?
     pub lexeme: String,
@@ -82,10 +98,18 @@ pub struct Error {
 impl fmt::Display for Error {
     // TODO: Maybe move this to a common error-printing trait across all error classes.
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "[{}] Error: {}.", self.place, self.kind)
+        write!(
+            f,
+            "[{}] Error: {}. [{}]",
+            self.place,
+            self.kind,
+            self.kind.code()
+        )
     }
 }
 
+impl std::error::Error for Error {}
+
 /// A specific kind of tokenization error.
 #[derive(Debug, Clone, PartialEq)]
 pub enum ErrorKind {
@@ -93,6 +117,23 @@ pub enum ErrorKind {
     UnexpectedCharacter(char),
     /// A double-quoted string was still open at the end of the file.
     UnterminatedString,
+    /// A `/*` block comment was still open at the end of the file.
+    UnterminatedComment,
+    /// A number ran straight into letters; the whole lexeme is included.
+    MalformedNumber(String),
+}
+
+impl ErrorKind {
+    /// The diagnostic code for this kind of error, which can be looked up with `--explain`.
+    pub fn code(&self) -> &'static str {
+        use ErrorKind::*;
+        match self {
+            UnexpectedCharacter(_) => "E001",
+            UnterminatedString => "E002",
+            UnterminatedComment => "E003",
+            MalformedNumber(_) => "E004",
+        }
+    }
 }
 
 impl fmt::Display for ErrorKind {
@@ -101,36 +142,127 @@ impl fmt::Display for ErrorKind {
         match self {
             UnexpectedCharacter(ch) => write!(f, "unexpected character {:?}", ch),
             UnterminatedString => write!(f, "unterminated string"),
+            UnterminatedComment => write!(f, "unterminated block comment"),
+            MalformedNumber(lexeme) => write!(f, "malformed number {:?}", lexeme),
         }
     }
 }
 
-/// Lex some Lox source into a vec of tokens and tokenization errors.
+/// Options controlling optional lexer behavior.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LexOptions {
+    /// Distance between tab stops, for calculating the columns of tokens.
+    pub tab_width: usize,
+    /// Produce tokens for comments, for tools such as formatters, rather than dropping them.
+    pub keep_comments: bool,
+}
+
+impl Default for LexOptions {
+    fn default() -> Self {
+        LexOptions {
+            tab_width: DEFAULT_TAB_WIDTH,
+            keep_comments: false,
+        }
+    }
+}
+
+/// Lex some Lox source into a vec of tokens and tokenization errors, with default options.
 pub fn lex(source: &str) -> Vec<Result<Token, Error>> {
-    let mut scan = Scan::new(source);
-    let mut result = Vec::new();
-    while !scan.is_empty() {
+    lex_iter(source).collect()
+}
+
+/// Lex some Lox source lazily, with default options, yielding each token or error as it's
+/// found.
+///
+/// This gives the same results as [lex], without holding them all in memory, and the caller
+/// can stop early.
+pub fn lex_iter(source: &str) -> impl Iterator<Item = Result<Token, Error>> + '_ {
+    let options = LexOptions::default();
+    let mut scan = Scan::with_tab_width(source, options.tab_width);
+    std::iter::from_fn(move || next_token(&mut scan, &options))
+}
+
+/// Lex some Lox source into tokens, or else all the errors if there were any.
+///
+/// This suits callers that can't go on with only some of the tokens.
+pub fn tokenize(source: &str) -> Result<Vec<Token>, Vec<Error>> {
+    let mut tokens = Vec::new();
+    let mut errors = Vec::new();
+    for result in lex_iter(source) {
+        match result {
+            Ok(token) => tokens.push(token),
+            Err(err) => errors.push(err),
+        }
+    }
+    if errors.is_empty() {
+        Ok(tokens)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Lex some Lox source into a vec of tokens and tokenization errors.
+pub fn lex_with(source: &str, options: &LexOptions) -> Vec<Result<Token, Error>> {
+    let mut scan = Scan::with_tab_width(source, options.tab_width);
+    std::iter::from_fn(|| next_token(&mut scan, options)).collect()
+}
+
+/// Lex the next token or error, skipping whitespace and, unless they're wanted, comments; or
+/// return None at the end of the input.
+fn next_token(scan: &mut Scan, options: &LexOptions) -> Option<Result<Token, Error>> {
+    loop {
+        if scan.is_empty() {
+            return None;
+        }
         scan.start_token();
         let tok = match scan.take().unwrap() {
             '\n' | ' ' | '\t' | '\r' => {
                 continue;
             }
             '+' => Tok::Plus,
+            '*' if scan.take_exactly('*') => Tok::StarStar,
             '*' => Tok::Star,
+            '%' => Tok::Percent,
             '-' => Tok::Minus,
             '.' => Tok::Dot,
             '/' if scan.take_exactly('/') => {
-                scan.take_until(|cc| *cc == '\n');
-                continue; // drop the comment
+                scan.take_while(|cc| !matches!(cc, '\n' | '\r'));
+                if !options.keep_comments {
+                    continue;
+                }
+                Tok::LineComment(scan.current_token()[2..].to_owned())
+            }
+            '/' if scan.take_exactly('*') => {
+                if !block_comment(scan) {
+                    return Some(Err(Error {
+                        place: scan.token_start(),
+                        kind: ErrorKind::UnterminatedComment,
+                    }));
+                }
+                if !options.keep_comments {
+                    continue;
+                }
+                let text = scan.current_token();
+                Tok::BlockComment(text[2..text.len() - 2].to_owned())
             }
             '/' => Tok::Slash,
             ';' => Tok::Semicolon,
+            '?' => Tok::Question,
+            ':' => Tok::Colon,
             ',' => Tok::Comma,
             '!' if scan.take_exactly('=') => Tok::BangEqual,
             '!' => Tok::Bang,
             '=' if scan.take_exactly('=') => Tok::EqualEqual,
             '=' => Tok::Equal,
-            '0'..='9' => number(&mut scan),
+            '0'..='9' => match number(scan) {
+                Ok(tok) => tok,
+                Err(kind) => {
+                    return Some(Err(Error {
+                        place: scan.token_start(),
+                        kind,
+                    }))
+                }
+            },
             '{' => Tok::LeftBrace,
             '}' => Tok::RightBrace,
             '(' => Tok::LeftParen,
@@ -139,47 +271,135 @@ pub fn lex(source: &str) -> Vec<Result<Token, Error>> {
             '<' => Tok::Less,
             '>' if scan.take_exactly('=') => Tok::GreaterEqual,
             '>' => Tok::Greater,
-            '"' => {
-                result.push(string(&mut scan));
-                continue;
-            }
-            ch if ch.is_ascii_alphabetic() || ch == '_' => word(&mut scan),
+            '"' => return Some(string(scan)),
+            ch if ch.is_ascii_alphabetic() || ch == '_' => word(scan),
             '#' if scan.next_column() == 2 && scan.take_exactly('!') => {
                 // drop shebang line
                 scan.take_until(|cc| *cc == '\n');
                 continue;
             }
             other => {
-                result.push(Err(Error {
+                return Some(Err(Error {
                     place: scan.token_start(),
                     kind: ErrorKind::UnexpectedCharacter(other),
-                }));
-                continue;
+                }))
             }
         };
-        result.push(Ok(Token {
+        return Some(Ok(Token {
             tok,
             lexeme: scan.current_token().to_owned(),
             place: scan.token_start(),
+            end: scan.next_place(),
         }));
     }
-    result
 }
 
-fn number(scan: &mut Scan) -> Tok {
-    scan.take_while(|c| c.is_ascii_digit());
+/// Lex a number, after its first digit has been taken.
+///
+/// A number running straight into letters, such as `123abc`, is an error rather than a number
+/// followed by an identifier.
+fn number(scan: &mut Scan) -> Result<Tok, ErrorKind> {
+    let radix = match scan.peek() {
+        Some('x' | 'X') => 16,
+        Some('o' | 'O') => 8,
+        Some('b' | 'B') => 2,
+        _ => 10,
+    };
+    let tok = if radix != 10
+        && scan.current_token() == "0"
+        && matches!(scan.peek_nth(1), Some(c) if c.is_digit(radix))
+    {
+        radix_number(scan, radix)
+    } else {
+        decimal_number(scan)
+    };
+    if scan
+        .take_if(|c| c.is_ascii_alphanumeric() || *c == '_')
+        .is_some()
+    {
+        scan.take_while(|c| c.is_ascii_alphanumeric() || *c == '_');
+        return Err(ErrorKind::MalformedNumber(scan.current_token().to_owned()));
+    }
+    tok.ok_or_else(|| ErrorKind::MalformedNumber(scan.current_token().to_owned()))
+}
+
+/// Lex the rest of a decimal number, after its first digit.
+///
+/// Digits in the integer and fractional parts may be separated by single underscores. Returns
+/// None if an underscore is misplaced.
+fn decimal_number(scan: &mut Scan) -> Option<Tok> {
+    scan.take_while(|c| c.is_ascii_digit() || *c == '_');
+    let mut separators_ok = separators_ok(scan.current_token());
     match scan.peek2() {
-        Some(('.', cc)) if cc.is_ascii_digit() => {
-            debug_assert!(scan.take_exactly('.'));
-            scan.take_while(|c| c.is_ascii_digit());
+        Some(('.', cc)) if cc.is_ascii_digit() || cc == '_' => {
+            scan.take();
+            let fraction_start = scan.current_token().len();
+            scan.take_while(|c| c.is_ascii_digit() || *c == '_');
+            separators_ok &= self::separators_ok(&scan.current_token()[fraction_start..]);
         }
         _ => (),
     }
-    // TODO: 1234hello should probably be an error, not a number followed by an identifier.
-    // But 1234+hello is ok.
+    // An exponent is only taken if there's at least one digit after the `e` and optional sign.
+    let exponent_digits_at = match (scan.peek_nth(0), scan.peek_nth(1)) {
+        (Some('e' | 'E'), Some('+' | '-')) => 2,
+        (Some('e' | 'E'), _) => 1,
+        _ => 0,
+    };
+    if exponent_digits_at > 0
+        && matches!(scan.peek_nth(exponent_digits_at), Some(c) if c.is_ascii_digit())
+    {
+        for _ in 0..exponent_digits_at {
+            scan.take();
+        }
+        scan.take_while(|c| c.is_ascii_digit());
+    }
+    if !separators_ok {
+        return None;
+    }
     // TODO: Error if the f64 parse fails (but I don't think it ever can?)
-    let val: f64 = scan.current_token().parse().unwrap();
-    Tok::Number(val)
+    let val: f64 = scan.current_token().replace('_', "").parse().unwrap();
+    Some(Tok::Number(val))
+}
+
+/// True if underscores in a run of digits are only single separators between digits.
+fn separators_ok(digits: &str) -> bool {
+    !digits.starts_with('_') && !digits.ends_with('_') && !digits.contains("__")
+}
+
+/// Consume the rest of a block comment, after the opening `/*`, up to and including
+/// the closing `*/`.
+///
+/// Returns false if the input ended before the comment was closed.
+fn block_comment(scan: &mut Scan) -> bool {
+    while let Some(c) = scan.take() {
+        if c == '*' && scan.take_exactly('/') {
+            return true;
+        }
+    }
+    false
+}
+
+/// Lex the rest of a hex, octal, or binary number, after the leading `0`, when it's known that
+/// a radix letter and at least one digit follow.
+///
+/// Digits may be separated by single underscores. Returns None if an underscore is misplaced.
+fn radix_number(scan: &mut Scan, radix: u32) -> Option<Tok> {
+    scan.take();
+    scan.take_while(|c| c.is_digit(radix) || *c == '_');
+    let digits = &scan.current_token()[2..];
+    if !separators_ok(digits) {
+        return None;
+    }
+    let digits: String = digits.chars().filter(|c| *c != '_').collect();
+    // Literals too large for a u64 can still be approximately represented by an f64.
+    let val = u64::from_str_radix(&digits, radix)
+        .map(|v| v as f64)
+        .unwrap_or_else(|_| {
+            digits.chars().fold(0.0, |acc, c| {
+                acc * radix as f64 + c.to_digit(radix).unwrap() as f64
+            })
+        });
+    Some(Tok::Number(val))
 }
 
 fn string(scan: &mut Scan) -> Result<Token, Error> {
@@ -189,6 +409,9 @@ fn string(scan: &mut Scan) -> Result<Token, Error> {
         s.push(c)
     }
     if !scan.take_exactly('"') {
+        // Everything up to the end of the input is in the string, so nothing after the
+        // opening quote is lexed as a token.
+        debug_assert!(scan.is_empty());
         return Err(Error {
             place: scan.token_start(),
             kind: ErrorKind::UnterminatedString,
@@ -197,6 +420,7 @@ fn string(scan: &mut Scan) -> Result<Token, Error> {
     Ok(Token {
         tok: Tok::String(s),
         place: scan.token_start(),
+        end: scan.next_place(),
         lexeme: scan.current_token().to_owned(),
     })
 }
@@ -205,7 +429,10 @@ fn word(scan: &mut Scan) -> Tok {
     scan.take_while(|c| c.is_ascii_alphanumeric() || *c == '_');
     match scan.current_token() {
         "and" => Tok::And,
+        "break" => Tok::Break,
         "class" => Tok::Class,
+        "const" => Tok::Const,
+        "continue" => Tok::Continue,
         "else" => Tok::Else,
         "false" => Tok::False,
         "for" => Tok::For,
@@ -235,7 +462,7 @@ mod test {
         results.into_iter().map(Result::unwrap).collect()
     }
 
-    fn lex_toks<'s>(s: &'s str) -> Vec<Tok> {
+    fn lex_toks(s: &str) -> Vec<Tok> {
         lex_tokens(s).into_iter().map(|t| t.tok).collect()
     }
 
@@ -245,7 +472,8 @@ mod test {
             lex_tokens("12345"),
             &[Token {
                 tok: Tok::Number(12345.0),
-                place: Place::new(1, 1),
+                place: Place::new(1, 1, 0),
+                end: Place::new(1, 6, 5),
                 lexeme: "12345".to_owned(),
             }],
         );
@@ -257,10 +485,230 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::approx_constant)] // Just a number, not meant to be pi.
     fn decimal_float() {
         assert_eq!(lex_toks("3.1415"), vec![Tok::Number(3.1415),]);
     }
 
+    #[test]
+    fn scientific_notation() {
+        assert_eq!(
+            lex_toks("6.022e23 1e+5 1E-10 2E3"),
+            vec![
+                Tok::Number(6.022e23),
+                Tok::Number(1e5),
+                Tok::Number(1e-10),
+                Tok::Number(2000.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn extreme_exponents() {
+        assert_eq!(
+            lex_toks("1e300 1e-300 1e400 1e-400"),
+            vec![
+                Tok::Number(1e300),
+                Tok::Number(1e-300),
+                Tok::Number(f64::INFINITY),
+                Tok::Number(0.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn exponent_without_digits_is_malformed() {
+        assert_eq!(
+            lex("1e 2e+"),
+            vec![
+                Err(Error {
+                    place: Place::new(1, 1, 0),
+                    kind: ErrorKind::MalformedNumber("1e".to_owned()),
+                }),
+                Err(Error {
+                    place: Place::new(1, 4, 3),
+                    kind: ErrorKind::MalformedNumber("2e".to_owned()),
+                }),
+                Ok(Token {
+                    tok: Tok::Plus,
+                    place: Place::new(1, 6, 5),
+                    end: Place::new(1, 7, 6),
+                    lexeme: "+".to_owned(),
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn exponent_stops_at_dot() {
+        assert_eq!(
+            lex_toks("1.5e3.2"),
+            vec![Tok::Number(1500.0), Tok::Dot, Tok::Number(2.0)]
+        );
+    }
+
+    #[test]
+    fn hex_numbers() {
+        assert_eq!(
+            lex_toks("0xff 0XFF 0x10 0xDeadBeef"),
+            vec![
+                Tok::Number(255.0),
+                Tok::Number(255.0),
+                Tok::Number(16.0),
+                Tok::Number(3735928559.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn octal_and_binary_numbers() {
+        assert_eq!(
+            lex_toks("0o17 0O777 0b1010 0B1"),
+            vec![
+                Tok::Number(15.0),
+                Tok::Number(511.0),
+                Tok::Number(10.0),
+                Tok::Number(1.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn underscores_separate_radix_digits() {
+        assert_eq!(
+            lex_toks("0xFF_FF 0b1010_1010 0o7_7_7"),
+            vec![Tok::Number(65535.0), Tok::Number(170.0), Tok::Number(511.0),]
+        );
+    }
+
+    #[test]
+    fn underscores_separate_decimal_digits() {
+        assert_eq!(
+            lex_toks("1_000 12_345.678_9 1_0e2"),
+            vec![
+                Tok::Number(1000.0),
+                Tok::Number(12345.6789),
+                Tok::Number(1000.0),
+            ]
+        );
+        assert_eq!(lex_toks("_1"), vec![Tok::Identifier("_1".to_owned())]);
+    }
+
+    #[test]
+    fn misplaced_underscores_in_decimal_numbers_are_malformed() {
+        let malformed = |lexeme: &str| ErrorKind::MalformedNumber(lexeme.to_owned());
+        assert_eq!(
+            lex("1_ 1__0 1_.5 1._5 1.5_ 1.5__5 1e1_0;")
+                .into_iter()
+                .map(|r| r.map(|t| t.tok).map_err(|e| e.kind))
+                .collect::<Vec<_>>(),
+            vec![
+                Err(malformed("1_")),
+                Err(malformed("1__0")),
+                Err(malformed("1_.5")),
+                Err(malformed("1._5")),
+                Err(malformed("1.5_")),
+                Err(malformed("1.5__5")),
+                // The exponent can't have separators.
+                Err(malformed("1e1_0")),
+                Ok(Tok::Semicolon),
+            ]
+        );
+    }
+
+    #[test]
+    fn misplaced_underscores_in_radix_numbers_are_malformed() {
+        let malformed = |lexeme: &str| ErrorKind::MalformedNumber(lexeme.to_owned());
+        assert_eq!(
+            lex("0xFF_ 0b1__0 0x_FF 0b1_;")
+                .into_iter()
+                .map(|r| r.map(|t| t.tok).map_err(|e| e.kind))
+                .collect::<Vec<_>>(),
+            vec![
+                Err(malformed("0xFF_")),
+                Err(malformed("0b1__0")),
+                Err(malformed("0x_FF")),
+                Err(malformed("0b1_")),
+                Ok(Tok::Semicolon),
+            ]
+        );
+    }
+
+    #[test]
+    fn digits_outside_radix_are_malformed() {
+        assert_eq!(
+            lex("0b102 0o78")
+                .into_iter()
+                .map(|r| r.unwrap_err().kind)
+                .collect::<Vec<_>>(),
+            vec![
+                ErrorKind::MalformedNumber("0b102".to_owned()),
+                ErrorKind::MalformedNumber("0o78".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn hex_number_too_large_for_u64() {
+        assert_eq!(
+            lex_toks("0x10000000000000000"),
+            vec![Tok::Number(18446744073709551616.0)]
+        );
+    }
+
+    #[test]
+    fn hex_prefix_without_digits_is_malformed() {
+        let malformed = |lexeme: &str| ErrorKind::MalformedNumber(lexeme.to_owned());
+        assert_eq!(
+            lex("0x 0xg 0xffz")
+                .into_iter()
+                .map(|r| r.unwrap_err().kind)
+                .collect::<Vec<_>>(),
+            vec![malformed("0x"), malformed("0xg"), malformed("0xffz")]
+        );
+    }
+
+    #[test]
+    fn number_followed_by_letters_is_malformed() {
+        assert_eq!(
+            lex("123abc_4 5_"),
+            [
+                Err(Error {
+                    place: Place::new(1, 1, 0),
+                    kind: ErrorKind::MalformedNumber("123abc_4".to_owned()),
+                }),
+                Err(Error {
+                    place: Place::new(1, 10, 9),
+                    kind: ErrorKind::MalformedNumber("5_".to_owned()),
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn number_separated_from_letters_is_fine() {
+        assert_eq!(
+            lex_toks("123 abc"),
+            [Tok::Number(123.0), Tok::Identifier("abc".to_owned())]
+        );
+        assert_eq!(
+            lex_toks("123+abc"),
+            [
+                Tok::Number(123.0),
+                Tok::Plus,
+                Tok::Identifier("abc".to_owned())
+            ]
+        );
+        assert_eq!(
+            lex_toks("1234.foo"),
+            [
+                Tok::Number(1234.0),
+                Tok::Dot,
+                Tok::Identifier("foo".to_owned())
+            ]
+        );
+    }
+
     #[test]
     fn skip_comments() {
         assert_eq!(
@@ -268,12 +716,14 @@ mod test {
             vec![
                 Token {
                     tok: Tok::Number(1.0),
-                    place: Place::new(1, 1),
+                    place: Place::new(1, 1, 0),
+                    end: Place::new(1, 2, 1),
                     lexeme: "1".to_owned(),
                 },
                 Token {
                     tok: Tok::Number(3.0),
-                    place: Place::new(4, 5),
+                    place: Place::new(4, 5, 28),
+                    end: Place::new(4, 10, 33),
                     lexeme: "3.000".to_owned()
                 },
             ]
@@ -290,13 +740,107 @@ mod test {
         assert_eq!(lex_tokens("// a comment\n\n\n// then another\n"), vec![]);
     }
 
+    #[test]
+    fn skip_block_comments() {
+        assert_eq!(
+            lex_tokens("1 /* two\n * would be here **/ 3\n/**/4/* */"),
+            vec![
+                Token {
+                    tok: Tok::Number(1.0),
+                    place: Place::new(1, 1, 0),
+                    end: Place::new(1, 2, 1),
+                    lexeme: "1".to_owned(),
+                },
+                Token {
+                    tok: Tok::Number(3.0),
+                    place: Place::new(2, 22, 30),
+                    end: Place::new(2, 23, 31),
+                    lexeme: "3".to_owned(),
+                },
+                Token {
+                    tok: Tok::Number(4.0),
+                    place: Place::new(3, 5, 36),
+                    end: Place::new(3, 6, 37),
+                    lexeme: "4".to_owned(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn block_comment_is_not_nested() {
+        assert_eq!(lex_toks("/* /* */ */"), [Tok::Star, Tok::Slash]);
+    }
+
+    #[test]
+    fn comments_are_kept_if_requested() {
+        let options = LexOptions {
+            keep_comments: true,
+            ..LexOptions::default()
+        };
+        let src = "a // line\r\n/* block\n*/ b //\n/**/";
+        let tokens: Vec<Token> = lex_with(src, &options)
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        let summary: Vec<(&Tok, Place, &str)> = tokens
+            .iter()
+            .map(|t| (&t.tok, t.place, t.lexeme.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (&Tok::Identifier("a".to_owned()), Place::new(1, 1, 0), "a"),
+                (
+                    &Tok::LineComment(" line".to_owned()),
+                    Place::new(1, 3, 2),
+                    "// line"
+                ),
+                (
+                    &Tok::BlockComment(" block\n".to_owned()),
+                    Place::new(2, 1, 11),
+                    "/* block\n*/"
+                ),
+                (&Tok::Identifier("b".to_owned()), Place::new(3, 4, 23), "b"),
+                (&Tok::LineComment("".to_owned()), Place::new(3, 6, 25), "//"),
+                (
+                    &Tok::BlockComment("".to_owned()),
+                    Place::new(4, 1, 28),
+                    "/**/"
+                ),
+            ]
+        );
+        assert_eq!(tokens[1].end, Place::new(1, 10, 9));
+        assert_eq!(lex_toks(src), lex_toks("a b"));
+    }
+
+    #[test]
+    fn unterminated_block_comment_error() {
+        assert_eq!(
+            lex("1\n  /* going along *\n"),
+            [
+                Ok(Token {
+                    tok: Tok::Number(1.0),
+                    place: Place::new(1, 1, 0),
+                    end: Place::new(1, 2, 1),
+                    lexeme: "1".to_owned(),
+                }),
+                Err(Error {
+                    kind: ErrorKind::UnterminatedComment,
+                    place: Place::new(2, 3, 4),
+                })
+            ]
+        );
+    }
+
     #[test]
     fn simple_string() {
         assert_eq!(
             lex_tokens(r#""hello Lox?""#),
             vec![Token {
                 tok: Tok::String("hello Lox?".to_owned()),
-                place: Place::new(1, 1),
+                place: Place::new(1, 1, 0),
+                end: Place::new(1, 13, 12),
                 lexeme: r#""hello Lox?""#.to_owned(),
             }]
         );
@@ -309,7 +853,8 @@ mod test {
             lex_tokens(src),
             vec![Token {
                 tok: Tok::String("one\nokapi\ntwo\n".to_owned()),
-                place: Place::new(1, 1),
+                place: Place::new(1, 1, 0),
+                end: Place::new(4, 2, 16),
                 lexeme: src.to_owned(),
             }]
         );
@@ -326,6 +871,30 @@ mod test {
         );
     }
 
+    #[test]
+    fn unterminated_string_takes_the_rest_of_the_input() {
+        let results = lex("print \"abc + 1;\nvar x = @; // \n  123");
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].as_ref().unwrap().tok, Tok::Print);
+        assert_eq!(
+            results[1],
+            Err(Error {
+                kind: ErrorKind::UnterminatedString,
+                place: Place::new(1, 7, 6),
+            })
+        );
+    }
+
+    #[test]
+    fn error_display_includes_code() {
+        let err = lex("\"going along...").pop().unwrap().unwrap_err();
+        assert_eq!(err.kind.code(), "E002");
+        assert_eq!(
+            err.to_string(),
+            "[line 1 column 1] Error: unterminated string. [E002]"
+        );
+    }
+
     #[test]
     fn words_and_keywords() {
         let src = "true false maybe __secret__";
@@ -340,12 +909,84 @@ mod test {
         );
     }
 
+    #[test]
+    fn const_keyword() {
+        assert_eq!(
+            lex_toks("const constant"),
+            [Tok::Const, Tok::Identifier("constant".to_owned())]
+        );
+    }
+
+    #[test]
+    fn loop_control_keywords() {
+        assert_eq!(lex_toks("break;"), [Tok::Break, Tok::Semicolon]);
+        assert_eq!(lex_toks("continue;"), [Tok::Continue, Tok::Semicolon]);
+        assert_eq!(
+            lex_toks("breakfast continued"),
+            [
+                Tok::Identifier("breakfast".to_owned()),
+                Tok::Identifier("continued".to_owned())
+            ]
+        );
+    }
+
     #[test]
     fn operators() {
-        let src = "+-*/";
+        let src = "+-*/%";
         assert_eq!(
             lex_toks(src),
-            [Tok::Plus, Tok::Minus, Tok::Star, Tok::Slash]
+            [Tok::Plus, Tok::Minus, Tok::Star, Tok::Slash, Tok::Percent]
+        );
+    }
+
+    #[test]
+    fn token_end_is_after_last_character() {
+        assert_eq!(
+            lex_tokens("a >= b"),
+            [
+                Token {
+                    tok: Tok::Identifier("a".to_owned()),
+                    place: Place::new(1, 1, 0),
+                    end: Place::new(1, 2, 1),
+                    lexeme: "a".to_owned(),
+                },
+                Token {
+                    tok: Tok::GreaterEqual,
+                    place: Place::new(1, 3, 2),
+                    end: Place::new(1, 5, 4),
+                    lexeme: ">=".to_owned(),
+                },
+                Token {
+                    tok: Tok::Identifier("b".to_owned()),
+                    place: Place::new(1, 6, 5),
+                    end: Place::new(1, 7, 6),
+                    lexeme: "b".to_owned(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn star_star_is_one_token() {
+        assert_eq!(
+            lex_toks("2 ** 3"),
+            [Tok::Number(2.0), Tok::StarStar, Tok::Number(3.0)]
+        );
+        assert_eq!(lex_toks("* *"), [Tok::Star, Tok::Star]);
+        assert_eq!(lex_toks("***"), [Tok::StarStar, Tok::Star]);
+    }
+
+    #[test]
+    fn ternary_punctuation() {
+        assert_eq!(
+            lex_toks("a?b:c"),
+            [
+                Tok::Identifier("a".to_owned()),
+                Tok::Question,
+                Tok::Identifier("b".to_owned()),
+                Tok::Colon,
+                Tok::Identifier("c".to_owned()),
+            ]
         );
     }
 
@@ -373,6 +1014,64 @@ between\tthese\t\twords
         );
     }
 
+    #[test]
+    fn offsets_count_bytes_but_columns_count_chars() {
+        assert_eq!(
+            lex("é+"),
+            [
+                Err(Error {
+                    place: Place::new(1, 1, 0),
+                    kind: ErrorKind::UnexpectedCharacter('é'),
+                }),
+                Ok(Token {
+                    tok: Tok::Plus,
+                    place: Place::new(1, 2, 2),
+                    end: Place::new(1, 3, 3),
+                    lexeme: "+".to_owned(),
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn tab_width_option_changes_columns() {
+        let src = "\tx";
+        let token = |column| {
+            Ok(Token {
+                tok: Tok::Identifier("x".to_owned()),
+                place: Place::new(1, column, 1),
+                end: Place::new(1, column + 1, 2),
+                lexeme: "x".to_owned(),
+            })
+        };
+        assert_eq!(lex_with(src, &LexOptions::default()), [token(9)]);
+        let options = LexOptions {
+            tab_width: 4,
+            ..LexOptions::default()
+        };
+        assert_eq!(lex_with(src, &options), [token(5)]);
+    }
+
+    #[test]
+    fn crlf_is_one_newline() {
+        assert_eq!(
+            lex_tokens("a\r\nb\r\n\r\n  c")
+                .iter()
+                .map(|t| t.place)
+                .collect::<Vec<_>>(),
+            [
+                Place::new(1, 1, 0),
+                Place::new(2, 1, 3),
+                Place::new(4, 3, 10)
+            ]
+        );
+    }
+
+    #[test]
+    fn lone_cr_is_a_newline() {
+        assert_eq!(lex_tokens("a\rb")[1].place, Place::new(2, 1, 2));
+    }
+
     #[test]
     fn ignore_shebang() {
         let tokens = lex_tokens("#! mbplox --yolo\n#! maybe also a second line\n123\n");
@@ -380,12 +1079,82 @@ between\tthese\t\twords
             tokens,
             [Token {
                 tok: Tok::Number(123.0),
-                place: Place::new(3, 1),
+                place: Place::new(3, 1, 45),
+                end: Place::new(3, 4, 48),
                 lexeme: "123".to_owned(),
             }]
         );
     }
 
+    #[test]
+    fn lex_iter_matches_lex() {
+        let src = "var a = 1;\n@ \"unterminated";
+        assert_eq!(lex_iter(src).collect::<Vec<_>>(), lex(src));
+        assert_eq!(lex_iter(src).count(), 7);
+    }
+
+    #[test]
+    fn error_is_a_std_error() {
+        let err = lex("@").pop().unwrap().unwrap_err();
+        let boxed: Box<dyn std::error::Error> = Box::new(err.clone());
+        assert_eq!(
+            boxed.to_string(),
+            "[line 1 column 1] Error: unexpected character '@'. [E001]"
+        );
+
+        fn first_token(source: &str) -> anyhow::Result<Token> {
+            Ok(lex_iter(source).next().unwrap()?)
+        }
+        let err = first_token("#").unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Error>().unwrap().kind,
+            ErrorKind::UnexpectedCharacter('#')
+        );
+    }
+
+    #[test]
+    fn lex_iter_can_stop_early() {
+        let mut tokens = lex_iter("print 1; \"never closed");
+        assert_eq!(tokens.next().unwrap().unwrap().tok, Tok::Print);
+        assert_eq!(tokens.next().unwrap().unwrap().tok, Tok::Number(1.0));
+        drop(tokens);
+        assert_eq!(
+            lex_iter("a b c")
+                .take(2)
+                .map(|r| r.unwrap().lexeme)
+                .collect::<Vec<_>>(),
+            ["a", "b"]
+        );
+        assert!(lex_iter("").next().is_none());
+    }
+
+    #[test]
+    fn tokenize_returns_all_tokens() {
+        let tokens = tokenize("print 1;").unwrap();
+        assert_eq!(
+            tokens.iter().map(|t| &t.tok).collect::<Vec<_>>(),
+            [&Tok::Print, &Tok::Number(1.0), &Tok::Semicolon]
+        );
+        assert_eq!(tokenize(""), Ok(vec![]));
+    }
+
+    #[test]
+    fn tokenize_returns_only_errors_if_there_are_any() {
+        assert_eq!(
+            tokenize("a @ b #"),
+            Err(vec![
+                Error {
+                    place: Place::new(1, 3, 2),
+                    kind: ErrorKind::UnexpectedCharacter('@'),
+                },
+                Error {
+                    place: Place::new(1, 7, 6),
+                    kind: ErrorKind::UnexpectedCharacter('#'),
+                },
+            ])
+        );
+    }
+
     #[test]
     fn lex_result_mixes_tokens_and_multiple_errors_in_order() {
         let unexpected_hash = ErrorKind::UnexpectedCharacter('#');
@@ -394,20 +1163,22 @@ between\tthese\t\twords
             [
                 Ok(Token {
                     tok: Tok::Identifier("hash".to_owned()),
-                    place: Place::new(1, 1),
+                    place: Place::new(1, 1, 0),
+                    end: Place::new(1, 5, 4),
                     lexeme: "hash".to_owned(),
                 }),
                 Err(Error {
-                    place: Place::new(1, 5),
+                    place: Place::new(1, 5, 4),
                     kind: unexpected_hash.clone(),
                 }),
                 Err(Error {
-                    place: Place::new(1, 6),
+                    place: Place::new(1, 6, 5),
                     kind: unexpected_hash,
                 }),
                 Ok(Token {
                     tok: Tok::Identifier("bang".to_owned()),
-                    place: Place::new(1, 7),
+                    place: Place::new(1, 7, 6),
+                    end: Place::new(1, 11, 10),
                     lexeme: "bang".to_owned(),
                 }),
             ]
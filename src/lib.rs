@@ -0,0 +1,46 @@
+// Copyright 2021 Martin Pool
+
+//! An interpreter for the Lox small language from *Crafting Interpreters*.
+//!
+//! The `mbplox` binary runs Lox programs from the command line. This library lets other
+//! programs embed the interpreter:
+//!
+//! ```
+//! let value = mbplox::run("var a = 6; a * 7").unwrap();
+//! assert_eq!(value, mbplox::Value::Number(42.0));
+//! ```
+
+use std::io::Write;
+
+use anyhow::Result;
+
+pub mod ast;
+pub mod diagnostic;
+pub mod eval;
+pub mod explain;
+pub mod lex;
+mod native;
+pub mod parse;
+pub mod place;
+pub mod resolve;
+mod scan;
+pub mod value;
+
+pub use crate::eval::{CompileErrors, Interpreter, RuntimeError};
+pub use crate::lex::{lex, lex_iter, tokenize};
+pub use crate::parse::parse_program;
+pub use crate::value::Value;
+
+/// Run Lox source in a new interpreter, printing to stdout, and return the value of the last
+/// statement if it's an expression statement, or otherwise nil.
+pub fn run(source: &str) -> Result<Value> {
+    Ok(Interpreter::new().eval(source)?.unwrap_or(Value::Nil))
+}
+
+/// Run Lox source in a new interpreter, writing the output of `print` to `out`, and return
+/// the value of the last statement like [run].
+pub fn eval_with_output(source: &str, out: &mut dyn Write) -> Result<Value> {
+    Ok(Interpreter::with_output(Box::new(out))
+        .eval(source)?
+        .unwrap_or(Value::Nil))
+}
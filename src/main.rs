@@ -1,26 +1,21 @@
 // Copyright 2021 Martin Pool
 
-//! An interpreter for the Lox small language from *Crafting Interpreters*.
+//! Run Lox programs from the command line, or interactively.
 
 use std::fs;
-use std::path::PathBuf;
-
-use anyhow::{Context, Result};
-
-mod ast;
-mod eval;
-mod lex;
-mod parse;
-mod place;
-mod scan;
-mod value;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
 
+use anyhow::Result;
 use argh::FromArgs;
 
+use mbplox::diagnostic::source_snippet;
+use mbplox::{eval, explain, lex, parse, Value};
+
 #[derive(FromArgs)]
 /// Run a Lox program.
 struct Args {
-    /// file to interpret
+    /// file to interpret, or "-" to read stdin
     #[argh(positional)]
     file: Option<PathBuf>,
 
@@ -28,47 +23,355 @@ struct Args {
     #[argh(option, short = 'e')]
     eval: Vec<String>,
 
-    /// print all the tokens from the input, instead of running it.
+    /// print all the tokens from the input, instead of running it, and exit with status 65
+    /// if there are any lexer errors.
     #[argh(switch)]
     dump_tokens: bool,
+
+    /// print the syntax tree of each statement, instead of running it, and exit with status
+    /// 65 if there are any errors.
+    #[argh(switch)]
+    dump_ast: bool,
+
+    /// check the program for errors without running it, and exit with status 65 if there
+    /// are any.
+    #[argh(switch)]
+    check: bool,
+
+    /// exit with the value of the last expression, if it's a number from 0 to 255.
+    #[argh(switch)]
+    exit_value: bool,
+
+    /// print how long each phase of running the program took, to stderr.
+    #[argh(switch)]
+    time: bool,
+
+    /// print a longer explanation of a diagnostic code, such as E001.
+    #[argh(option)]
+    explain: Option<String>,
+
+    /// prompt for each new statement in the interactive interpreter (default "> ").
+    #[argh(option)]
+    prompt: Option<String>,
+
+    /// prompt for each further line of an incomplete statement in the interactive
+    /// interpreter (default "... ").
+    #[argh(option)]
+    continuation_prompt: Option<String>,
+
+    /// read chained comparisons as in mathematics, so that `a < b < c` means
+    /// `a < b and b < c`.
+    #[argh(switch)]
+    math_chains: bool,
 }
 
+/// The stack size of the thread that runs the interpreter.
+///
+/// Evaluation recurses through nested calls, so this is enough for
+/// [eval::MAX_CALL_DEPTH] calls with room to spare, even in a debug build.
+const STACK_SIZE: usize = 256 << 20;
+
 fn main() -> Result<()> {
-    let args: Args = argh::from_env();
+    std::thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(run)?
+        .join()
+        // The panic message has already been printed.
+        .unwrap_or_else(|_| std::process::exit(101))
+}
+
+/// Do whatever the command line asks.
+fn run() -> Result<()> {
+    let args = parse_args();
+    if let Some(code) = &args.explain {
+        match explain::explain(code) {
+            Some(explanation) => {
+                println!("{}", explanation);
+                return Ok(());
+            }
+            None => {
+                eprintln!("error: no explanation for diagnostic code {:?}", code);
+                std::process::exit(ExitCode::Usage as i32);
+            }
+        }
+    }
+    let parse_options = parse::ParseOptions {
+        math_chains: args.math_chains,
+    };
     let mut all_sources: Vec<String> = Vec::new();
     if args.file.is_none() && args.eval.is_empty() {
-        eprintln!(
-            "error: repl is not implemented yet: suppply either a source file name or --eval arguments"
+        if args.dump_tokens || args.dump_ast || args.check || args.exit_value {
+            eprintln!("error: supply either a source file name or --eval arguments");
+            std::process::exit(ExitCode::Usage as i32);
+        }
+        return repl(
+            &Prompts::new(args.prompt, args.continuation_prompt),
+            parse_options,
         );
-        std::process::exit(ExitCode::Usage as i32);
     }
     if let Some(path) = &args.file {
-        all_sources.push(fs::read_to_string(path).context("read source file")?);
+        let read = if path.as_os_str() == "-" {
+            io::read_to_string(io::stdin())
+        } else {
+            fs::read_to_string(path)
+        };
+        match read {
+            Ok(source) => all_sources.push(source),
+            Err(err) => {
+                eprintln!("error: failed to read {}: {}", path.display(), err);
+                std::process::exit(ExitCode::NoInput as i32);
+            }
+        }
     }
     all_sources.extend(args.eval);
-    // TODO: If no sources then repl.
     if args.dump_tokens {
+        let mut any_errors = false;
         for source in &all_sources {
             for r in lex::lex(source) {
                 match r {
                     Ok(token) => println!("{:?}", token.tok),
-                    Err(err) => println!("{}", err),
-                    // TODO: Remember we saw an error, and set the exit code.
+                    Err(err) => {
+                        println!("{}", err);
+                        any_errors = true;
+                    }
+                }
+            }
+        }
+        if any_errors {
+            std::process::exit(ExitCode::DataErr as i32);
+        }
+    } else if args.dump_ast {
+        let mut any_errors = false;
+        for source in &all_sources {
+            let mut tokens = Vec::new();
+            for r in lex::lex(source) {
+                match r {
+                    Ok(token) => tokens.push(token),
+                    Err(err) => {
+                        println!("{}", err);
+                        any_errors = true;
+                    }
                 }
             }
+            let (stmts, errors) = parse::parse_program_with_options(&tokens, parse_options);
+            for err in &errors {
+                println!("{}", err);
+                any_errors = true;
+            }
+            for stmt in &stmts {
+                print!("{}", stmt.tree());
+            }
+        }
+        if any_errors {
+            std::process::exit(ExitCode::DataErr as i32);
+        }
+    } else if args.check {
+        let mut interpreter = eval::Interpreter::new();
+        interpreter.set_parse_options(parse_options);
+        let mut any_errors = false;
+        for source in &all_sources {
+            if let Err(err) = interpreter.check(source) {
+                print_compile_errors(source, &err);
+                eprintln!("error: {}", err);
+                any_errors = true;
+            }
+        }
+        if args.time {
+            print_timings(&interpreter.timings());
+        }
+        if any_errors {
+            std::process::exit(ExitCode::DataErr as i32);
         }
     } else {
         let mut interpreter = eval::Interpreter::new();
+        interpreter.set_parse_options(parse_options);
+        let mut last_value = None;
         for source in &all_sources {
-            let value = interpreter.eval(source)?;
-            println!("{}", value);
+            let value = match interpreter.eval(source) {
+                Ok(value) => value,
+                Err(err) => {
+                    if let Some(runtime_error) = err.downcast_ref::<eval::RuntimeError>() {
+                        eprintln!("{}", runtime_error);
+                        eprint!("{}", source_snippet(source, runtime_error.place));
+                    } else {
+                        print_compile_errors(source, &err);
+                        eprintln!("error: {}", err);
+                    }
+                    if args.time {
+                        print_timings(&interpreter.timings());
+                    }
+                    std::process::exit(ExitCode::DataErr as i32);
+                }
+            };
+            if let Some(value) = value {
+                println!("{}", value);
+                last_value = Some(value);
+            }
+        }
+        if args.time {
+            print_timings(&interpreter.timings());
+        }
+        if args.exit_value {
+            match last_value {
+                Some(Value::Number(n)) => std::process::exit(n.trunc().clamp(0.0, 255.0) as i32),
+                Some(other) => eprintln!(
+                    "warning: --exit-value: result is a {}, not a number",
+                    other.type_name()
+                ),
+                None => (),
+            }
         }
     }
     Ok(())
 }
 
+/// Parse the command line, like [argh::from_env], but also accepting "-" as the file name.
+///
+/// argh doesn't accept "-" as a positional argument, so it's taken out here and put back
+/// afterwards.
+fn parse_args() -> Args {
+    let strings: Vec<String> = std::env::args().collect();
+    let cmd = Path::new(&strings[0])
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("mbplox");
+    let mut read_stdin = false;
+    let mut rest: Vec<&str> = Vec::new();
+    for (i, arg) in strings.iter().enumerate().skip(1) {
+        // "-" might also be the value of an option, such as `-e -`.
+        if arg == "-"
+            && !matches!(
+                strings[i - 1].as_str(),
+                "-e" | "--eval" | "--explain" | "--prompt" | "--continuation-prompt"
+            )
+        {
+            read_stdin = true;
+        } else {
+            rest.push(arg);
+        }
+    }
+    match Args::from_args(&[cmd], &rest) {
+        Ok(mut args) => {
+            if read_stdin {
+                if args.file.is_some() {
+                    eprintln!("error: supply either a source file name or \"-\", not both");
+                    std::process::exit(ExitCode::Usage as i32);
+                }
+                args.file = Some(PathBuf::from("-"));
+            }
+            args
+        }
+        Err(early_exit) => match early_exit.status {
+            Ok(()) => {
+                println!("{}", early_exit.output);
+                std::process::exit(0);
+            }
+            Err(()) => {
+                eprintln!(
+                    "{}\nRun {} --help for more information.",
+                    early_exit.output, cmd
+                );
+                std::process::exit(1);
+            }
+        },
+    }
+}
+
+/// Print the time spent in each phase to stderr.
+fn print_timings(timings: &eval::Timings) {
+    eprintln!("lex:     {:>10.3?}", timings.lex);
+    eprintln!("parse:   {:>10.3?}", timings.parse);
+    eprintln!("resolve: {:>10.3?}", timings.resolve);
+    eprintln!("eval:    {:>10.3?}", timings.eval);
+}
+
+/// If `err` holds errors found before running `source`, print each of them with the source
+/// line where it was found.
+fn print_compile_errors(source: &str, err: &anyhow::Error) {
+    if let Some(errors) = err.downcast_ref::<eval::CompileErrors>() {
+        for compile_error in &errors.0 {
+            eprintln!("{}", compile_error);
+            if let Some(place) = compile_error.place() {
+                eprint!("{}", source_snippet(source, place));
+            }
+        }
+    }
+}
+
+/// The prompts shown by the interactive interpreter.
+struct Prompts {
+    /// Shown before the first line of each statement.
+    prompt: String,
+    /// Shown before each further line of an incomplete statement.
+    continuation: String,
+    /// True if either prompt was set on the command line, in which case they're shown
+    /// even if stdin is not a terminal.
+    explicit: bool,
+}
+
+impl Prompts {
+    fn new(prompt: Option<String>, continuation: Option<String>) -> Prompts {
+        Prompts {
+            explicit: prompt.is_some() || continuation.is_some(),
+            prompt: prompt.unwrap_or_else(|| "> ".to_owned()),
+            continuation: continuation.unwrap_or_else(|| "... ".to_owned()),
+        }
+    }
+}
+
+/// Read and evaluate lines from stdin until end of file, printing the value of each.
+///
+/// Errors are printed and then the next line is read, with interpreter state kept from one
+/// line to the next.
+///
+/// If the input so far is incomplete, such as an unclosed block, more lines are read and added
+/// to it, until it's complete, or a blank line is entered to give up.
+fn repl(prompts: &Prompts, parse_options: parse::ParseOptions) -> Result<()> {
+    let mut interpreter = eval::Interpreter::new();
+    interpreter.set_parse_options(parse_options);
+    let stdin = io::stdin();
+    let show_prompts = prompts.explicit || stdin.is_terminal();
+    let mut source = String::new();
+    let mut line = String::new();
+    loop {
+        if show_prompts {
+            if source.is_empty() {
+                print!("{}", prompts.prompt);
+            } else {
+                print!("{}", prompts.continuation);
+            }
+            io::stdout().flush()?;
+        }
+        line.clear();
+        let at_end = stdin.lock().read_line(&mut line)? == 0;
+        if at_end && source.is_empty() {
+            if show_prompts {
+                println!();
+            }
+            return Ok(());
+        }
+        source.push_str(&line);
+        let give_up = at_end || line.trim().is_empty();
+        if !give_up && lex::tokenize(&source).is_ok_and(|tokens| parse::is_incomplete(&tokens)) {
+            continue;
+        }
+        match interpreter.eval(&source) {
+            Ok(Some(value)) => println!("{}", value),
+            Ok(None) => (),
+            Err(err) => {
+                print_compile_errors(&source, &err);
+                eprintln!("{}", err);
+            }
+        }
+        source.clear();
+    }
+}
+
 /// Semantic exit codes, aligned with `<sysexits.h>`.
 // Not from the Rust `sysexits` crate because it currently does not build on Windows.
 enum ExitCode {
     Usage = 64,
+    DataErr = 65,
+    /// The source file could not be read.
+    NoInput = 66,
 }
@@ -0,0 +1,139 @@
+// Copyright 2021 Martin Pool
+
+//! Functions built in to the interpreter, implemented in Rust.
+
+use std::convert::TryFrom;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::eval::Interpreter;
+use crate::value::{NativeFn, Value};
+
+/// All the native functions, which are defined as globals when an interpreter starts.
+pub fn natives() -> Vec<Rc<NativeFn>> {
+    vec![
+        native("clock", 0, clock),
+        native("len", 1, len),
+        native("substr", 3, substr),
+        native("repeat", 2, repeat),
+        native("trim", 1, trim),
+        native("starts_with", 2, starts_with),
+        native("ends_with", 2, ends_with),
+        native("contains", 2, contains),
+        native("str", 1, str),
+        native("num", 1, num),
+        native("eval", 1, eval),
+    ]
+}
+
+fn native(
+    name: &'static str,
+    arity: usize,
+    fun: fn(&mut Interpreter<'_>, &[Value]) -> Result<Value, String>,
+) -> Rc<NativeFn> {
+    Rc::new(NativeFn { name, arity, fun })
+}
+
+/// Seconds since the Unix epoch, with a fractional part.
+fn clock(_interpreter: &mut Interpreter<'_>, _args: &[Value]) -> Result<Value, String> {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| Value::Number(d.as_secs_f64()))
+        .map_err(|err| format!("system clock is before the epoch: {}", err))
+}
+
+/// The number of chars in a string.
+fn len(_interpreter: &mut Interpreter<'_>, args: &[Value]) -> Result<Value, String> {
+    let s = string_arg("len", &args[0])?;
+    Ok(Value::from(s.chars().count()))
+}
+
+/// The `len` chars of a string starting from char `start`, counting from 0.
+fn substr(_interpreter: &mut Interpreter<'_>, args: &[Value]) -> Result<Value, String> {
+    let s = string_arg("substr", &args[0])?;
+    let start = index_arg("substr", "start", &args[1])?;
+    let len = index_arg("substr", "length", &args[2])?;
+    let char_count = s.chars().count();
+    if start.checked_add(len).is_none_or(|end| end > char_count) {
+        return Err(format!(
+            "substr: start {} and length {} are out of range for a string of length {}",
+            start, len, char_count
+        ));
+    }
+    Ok(Value::String(s.chars().skip(start).take(len).collect()))
+}
+
+/// A string repeated `count` times, which must be a non-negative whole number.
+fn repeat(_interpreter: &mut Interpreter<'_>, args: &[Value]) -> Result<Value, String> {
+    let s = string_arg("repeat", &args[0])?;
+    let count = index_arg("repeat", "count", &args[1])?;
+    if s.len().checked_mul(count).is_none() {
+        return Err(format!("repeat: {} copies would be too long", count));
+    }
+    Ok(Value::String(s.repeat(count)))
+}
+
+/// A string without leading or trailing whitespace.
+fn trim(_interpreter: &mut Interpreter<'_>, args: &[Value]) -> Result<Value, String> {
+    let s = string_arg("trim", &args[0])?;
+    Ok(Value::from(s.trim()))
+}
+
+/// True if the first string starts with the second.
+fn starts_with(_interpreter: &mut Interpreter<'_>, args: &[Value]) -> Result<Value, String> {
+    let s = string_arg("starts_with", &args[0])?;
+    let prefix = string_arg("starts_with", &args[1])?;
+    Ok(Value::Bool(s.starts_with(&prefix)))
+}
+
+/// True if the first string ends with the second.
+fn ends_with(_interpreter: &mut Interpreter<'_>, args: &[Value]) -> Result<Value, String> {
+    let s = string_arg("ends_with", &args[0])?;
+    let suffix = string_arg("ends_with", &args[1])?;
+    Ok(Value::Bool(s.ends_with(&suffix)))
+}
+
+/// True if the second string occurs anywhere in the first.
+fn contains(_interpreter: &mut Interpreter<'_>, args: &[Value]) -> Result<Value, String> {
+    let s = string_arg("contains", &args[0])?;
+    let needle = string_arg("contains", &args[1])?;
+    Ok(Value::Bool(s.contains(&needle)))
+}
+
+/// Any value as a string, the same as `print` would show it.
+fn str(_interpreter: &mut Interpreter<'_>, args: &[Value]) -> Result<Value, String> {
+    Ok(Value::String(args[0].to_string()))
+}
+
+/// Parse a string as a number, ignoring surrounding whitespace.
+///
+/// Returns nil if the string isn't a number, so that a program can check for that without
+/// stopping.
+fn num(_interpreter: &mut Interpreter<'_>, args: &[Value]) -> Result<Value, String> {
+    let s = string_arg("num", &args[0])?;
+    Ok(s.trim().parse().map_or(Value::Nil, Value::Number))
+}
+
+/// Run Lox source code in the global scope, returning the value of its last statement if
+/// that's an expression, or otherwise nil.
+fn eval(interpreter: &mut Interpreter<'_>, args: &[Value]) -> Result<Value, String> {
+    let source = string_arg("eval", &args[0])?;
+    interpreter.eval_nested(&source)
+}
+
+fn string_arg(fn_name: &str, value: &Value) -> Result<String, String> {
+    String::try_from(value.clone()).map_err(|err| format!("{}: {}", fn_name, err))
+}
+
+/// Convert an argument to a char index, which must be a non-negative whole number.
+fn index_arg(fn_name: &str, arg_name: &str, value: &Value) -> Result<usize, String> {
+    let n = f64::try_from(value.clone()).map_err(|err| format!("{}: {}", fn_name, err))?;
+    if n >= 0.0 && n.fract() == 0.0 && n <= usize::MAX as f64 {
+        Ok(n as usize)
+    } else {
+        Err(format!(
+            "{}: {} must be a non-negative whole number, not {}",
+            fn_name, arg_name, value
+        ))
+    }
+}
@@ -2,12 +2,133 @@
 
 //! Parse a stream of tokens into an AST.
 
-use anyhow::{anyhow, Result};
+use std::cell::Cell;
+use std::fmt;
+use std::rc::Rc;
 
-use crate::ast::Expr;
-use crate::lex::Token;
+use crate::ast::{BinaryOp, Expr, FunctionDecl, LogicalOp, Stmt, UnaryOp};
+use crate::lex::{Tok, Token};
+use crate::place::Place;
 use crate::value::Value;
 
+/// Options that change the language the parser accepts.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// Read chained comparisons as in mathematics, so that `a < b < c` means
+    /// `a < b and b < c`, rather than comparing the result of `a < b` to `c`.
+    pub math_chains: bool,
+}
+
+/// An error while parsing tokens.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Error {
+    /// Place in the source where the error occurred, or None if it was at the end of the input.
+    pub place: Option<Place>,
+    /// Type of parser error.
+    pub kind: ErrorKind,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.place {
+            Some(place) => write!(
+                f,
+                "[{}] Error: {}. [{}]",
+                place,
+                self.kind,
+                self.kind.code()
+            ),
+            None => write!(
+                f,
+                "[end of input] Error: {}. [{}]",
+                self.kind,
+                self.kind.code()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl Error {
+    /// True if the error is that the input ended too soon, so that more input might fix it.
+    pub fn is_at_end(&self) -> bool {
+        self.place.is_none()
+    }
+}
+
+/// A specific kind of parser error.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ErrorKind {
+    /// Expected some construct, but found a different token, whose lexeme is included.
+    Unexpected {
+        expected: &'static str,
+        found: String,
+    },
+    /// Expected some construct, but the input ended.
+    UnexpectedEnd { expected: &'static str },
+    /// Expected a token to close a construct opened by another token at `opener_place`.
+    Unmatched {
+        expected: &'static str,
+        opener: &'static str,
+        opener_place: Place,
+    },
+    /// The input ended inside a block or class body opened by the token at `opener_place`.
+    Unclosed {
+        opener: &'static str,
+        opener_place: Place,
+    },
+    /// The left side of an `=` is not a variable.
+    InvalidAssignmentTarget,
+    /// A call passes more than [MAX_ARGUMENTS] arguments, or a function declares more than
+    /// that many parameters.
+    TooMany { what: &'static str },
+}
+
+impl ErrorKind {
+    /// The diagnostic code for this kind of error, which can be looked up with `--explain`.
+    pub fn code(&self) -> &'static str {
+        use ErrorKind::*;
+        match self {
+            Unexpected { .. } => "E005",
+            UnexpectedEnd { .. } => "E006",
+            Unmatched { .. } => "E007",
+            InvalidAssignmentTarget => "E008",
+            TooMany { .. } => "E010",
+            Unclosed { .. } => "E017",
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ErrorKind::*;
+        match self {
+            Unexpected { expected, found } => write!(f, "expected {}, found '{}'", expected, found),
+            UnexpectedEnd { expected } => write!(f, "expected {}, found end of input", expected),
+            Unmatched {
+                expected,
+                opener,
+                opener_place,
+            } => write!(
+                f,
+                "expected {} to match {} at {}",
+                expected, opener, opener_place
+            ),
+            Unclosed {
+                opener,
+                opener_place,
+            } => write!(f, "unclosed {} opened at {}", opener, opener_place),
+            InvalidAssignmentTarget => write!(f, "invalid assignment target"),
+            TooMany { what } => write!(f, "can't have more than {} {}", MAX_ARGUMENTS, what),
+        }
+    }
+}
+
+/// The result of parsing some construct: the parsed value and the remaining tokens,
+/// or an error.
+type ParseResult<'t, T> = Result<(T, &'t [Token]), Error>;
+
 // General approach to the parser API:
 //
 // At every point of trying to parse something, it seems like
@@ -23,40 +144,1053 @@ use crate::value::Value;
 // This is intended to be in the parser combinator style, written
 // from scratch as a learning exercise...
 
+/// Parse a whole program: a sequence of declarations and statements.
+///
+///    program        → declaration* EOF ;
+///
+/// After an error, the parser skips from the token where the error was found to the next
+/// statement boundary and carries on, so that several independent errors can be reported at
+/// once. This happens inside blocks and class bodies too, so an error there doesn't also
+/// make the rest of the body look wrong. Returns all the statements that parsed successfully,
+/// and all the errors, in source order.
+pub fn parse_program(tokens: &[Token]) -> (Vec<Stmt>, Vec<Error>) {
+    parse_program_with_options(tokens, ParseOptions::default())
+}
+
+/// Parse a whole program as for [parse_program], with non-standard options.
+pub fn parse_program_with_options(
+    tokens: &[Token],
+    options: ParseOptions,
+) -> (Vec<Stmt>, Vec<Error>) {
+    let mut stmts = Vec::new();
+    let mut errors = Vec::new();
+    let mut rest = tokens;
+    while !rest.is_empty() {
+        match parse_declaration(rest, &mut errors) {
+            Ok((stmt, after)) => {
+                stmts.push(stmt);
+                rest = after;
+            }
+            Err(err) => {
+                let error_at = error_position(rest, &err);
+                errors.push(err);
+                rest = synchronize(&rest[error_at..]);
+            }
+        }
+    }
+    if options.math_chains {
+        stmts.iter_mut().for_each(chain_comparisons_in_stmt);
+    }
+    (stmts, errors)
+}
+
+/// Rewrite the chained comparisons in a newly parsed statement as [Expr::Chain], for
+/// [ParseOptions::math_chains].
+fn chain_comparisons_in_stmt(stmt: &mut Stmt) {
+    match stmt {
+        Stmt::Expression(expr)
+        | Stmt::Print {
+            value: Some(expr), ..
+        } => chain_comparisons(expr),
+        Stmt::Print { value: None, .. } | Stmt::Break { .. } | Stmt::Continue { .. } => {}
+        Stmt::Function(decl) => chain_comparisons_in_function(decl),
+        Stmt::Class { methods, .. } => methods.iter_mut().for_each(chain_comparisons_in_function),
+        Stmt::Block(stmts) => stmts.iter_mut().for_each(chain_comparisons_in_stmt),
+        Stmt::If {
+            cond,
+            then_branch,
+            else_branch,
+        } => {
+            chain_comparisons(cond);
+            chain_comparisons_in_stmt(then_branch);
+            else_branch
+                .iter_mut()
+                .for_each(|s| chain_comparisons_in_stmt(s));
+        }
+        Stmt::While {
+            cond,
+            body,
+            increment,
+        } => {
+            chain_comparisons(cond);
+            chain_comparisons_in_stmt(body);
+            increment.iter_mut().for_each(chain_comparisons);
+        }
+        Stmt::Return { value, .. }
+        | Stmt::Var {
+            initializer: value, ..
+        } => value.iter_mut().for_each(chain_comparisons),
+    }
+}
+
+fn chain_comparisons_in_function(decl: &mut Rc<FunctionDecl>) {
+    Rc::get_mut(decl)
+        .expect("newly parsed function is not shared")
+        .body
+        .iter_mut()
+        .for_each(chain_comparisons_in_stmt);
+}
+
+/// Rewrite the chained comparisons in a newly parsed expression as [Expr::Chain].
+///
+/// A comparison whose left operand is another comparison, not in parentheses, is a chain.
+fn chain_comparisons(expr: &mut Expr) {
+    match expr {
+        Expr::Literal(_) | Expr::Variable { .. } | Expr::This { .. } | Expr::Super { .. } => {}
+        Expr::Function(decl) => chain_comparisons_in_function(decl),
+        Expr::Assign { value, .. } => chain_comparisons(value),
+        Expr::Get { object, .. } => chain_comparisons(object),
+        Expr::Set { object, value, .. } => {
+            chain_comparisons(object);
+            chain_comparisons(value);
+        }
+        Expr::Call { callee, args, .. } => {
+            chain_comparisons(callee);
+            args.iter_mut().for_each(chain_comparisons);
+        }
+        Expr::Grouping { expr } | Expr::Unary { expr, .. } => chain_comparisons(expr),
+        Expr::Binary { left, right, .. } | Expr::Logical { left, right, .. } => {
+            chain_comparisons(left);
+            chain_comparisons(right);
+        }
+        Expr::Ternary {
+            cond,
+            then_expr,
+            else_expr,
+            ..
+        } => {
+            chain_comparisons(cond);
+            chain_comparisons(then_expr);
+            chain_comparisons(else_expr);
+        }
+        Expr::Chain { first, rest } => {
+            chain_comparisons(first);
+            rest.iter_mut()
+                .for_each(|(_, _, operand)| chain_comparisons(operand));
+        }
+    }
+    // The operands were rewritten first, so a longer chain on the left is already a Chain.
+    let is_chain = match expr {
+        Expr::Binary { op, left, .. } if is_comparison(op) => match left.as_ref() {
+            Expr::Binary { op, .. } => is_comparison(op),
+            Expr::Chain { .. } => true,
+            _ => false,
+        },
+        _ => false,
+    };
+    if !is_chain {
+        return;
+    }
+    if let Expr::Binary {
+        op,
+        place,
+        left,
+        right,
+    } = std::mem::replace(expr, Expr::Literal(Value::Nil))
+    {
+        let (first, mut rest) = match *left {
+            Expr::Binary {
+                op,
+                place,
+                left,
+                right,
+            } => (left, vec![(op, place, *right)]),
+            Expr::Chain { first, rest } => (first, rest),
+            _ => unreachable!(),
+        };
+        rest.push((op, place, *right));
+        *expr = Expr::Chain { first, rest };
+    }
+}
+
+fn is_comparison(op: &BinaryOp) -> bool {
+    matches!(
+        op,
+        BinaryOp::LessThan | BinaryOp::LessEqual | BinaryOp::GreaterThan | BinaryOp::GreaterEqual
+    )
+}
+
+/// The index in `tokens` of the token where `err` was found, or the length of `tokens` if
+/// it's at the end of the input.
+fn error_position(tokens: &[Token], err: &Error) -> usize {
+    tokens
+        .iter()
+        .position(|t| Some(t.place) == err.place)
+        .unwrap_or(tokens.len())
+}
+
+/// Handle an error while parsing the contents of a block or class body: record it in
+/// `errors` and return the tokens to carry on from.
+///
+/// An error at the end of the input is returned instead, since there's nothing left to
+/// recover with. If the error was found at a `}`, that's kept to close the body.
+fn recover_in_body<'t>(
+    tokens: &'t [Token],
+    err: Error,
+    errors: &mut Vec<Error>,
+) -> Result<&'t [Token], Error> {
+    if err.is_at_end() {
+        return Err(err);
+    }
+    let error_at = error_position(tokens, &err);
+    errors.push(err);
+    match tokens.get(error_at) {
+        Some(token) if token.tok == Tok::RightBrace => Ok(&tokens[error_at..]),
+        _ => Ok(synchronize(&tokens[error_at..])),
+    }
+}
+
+/// True if the program is unfinished: it doesn't parse, but only because it ends too soon.
+///
+/// For example, `fun f() {` is incomplete, but `fun f() { ) }` is just wrong.
+pub fn is_incomplete(tokens: &[Token]) -> bool {
+    let (_, errors) = parse_program(tokens);
+    !errors.is_empty() && errors.iter().all(Error::is_at_end)
+}
+
+/// Parse a declaration, or any other statement.
+///
+///    declaration    → classDecl | funDecl | varDecl | constDecl | statement ;
+///    classDecl      → "class" IDENTIFIER ( "<" IDENTIFIER )? "{" function* "}" ;
+///    funDecl        → "fun" function ;
+///    varDecl        → "var" IDENTIFIER ( "=" expression )? ";" ;
+///    constDecl      → "const" IDENTIFIER "=" expression ";" ;
+///
+/// Errors that are recovered from inside blocks are added to `errors`.
+fn parse_declaration<'t>(tokens: &'t [Token], errors: &mut Vec<Error>) -> ParseResult<'t, Stmt> {
+    if let Some(rest) = take_tok(tokens, &Tok::Class) {
+        parse_class(rest, errors)
+    } else if let Some(rest) = take_tok(tokens, &Tok::Fun)
+        .filter(|rest| !matches!(rest.first(), Some(token) if token.tok == Tok::LeftParen))
+    {
+        // `fun (` starts an anonymous function, in an expression statement.
+        let (decl, rest) = parse_function(rest, errors)?;
+        Ok((Stmt::Function(Rc::new(decl)), rest))
+    } else if let Some(rest) = take_tok(tokens, &Tok::Var) {
+        let ((name, place), rest) = take_if(rest, |t| identifier(t).map(|name| (name, t.place)))
+            .ok_or_else(|| unexpected(rest, "variable name"))?;
+        if let Some(rest) = take_tok(rest, &Tok::Equal) {
+            let (initializer, rest) =
+                parse_terminated_expression(rest, "';' after variable declaration")?;
+            Ok((
+                Stmt::Var {
+                    name,
+                    place,
+                    initializer: Some(initializer),
+                    constant: false,
+                },
+                rest,
+            ))
+        } else {
+            let rest = end_statement(rest, "';' after variable declaration")?;
+            Ok((
+                Stmt::Var {
+                    name,
+                    place,
+                    initializer: None,
+                    constant: false,
+                },
+                rest,
+            ))
+        }
+    } else if let Some(rest) = take_tok(tokens, &Tok::Const) {
+        // A constant must be given its value when it's declared.
+        let ((name, place), rest) = take_if(rest, |t| identifier(t).map(|name| (name, t.place)))
+            .ok_or_else(|| unexpected(rest, "constant name"))?;
+        let rest = expect_tok(rest, &Tok::Equal, "'=' after constant name")?;
+        let (initializer, rest) =
+            parse_terminated_expression(rest, "';' after constant declaration")?;
+        Ok((
+            Stmt::Var {
+                name,
+                place,
+                initializer: Some(initializer),
+                constant: true,
+            },
+            rest,
+        ))
+    } else {
+        parse_statement(tokens, errors)
+    }
+}
+
+/// Parse a class's name, superclass, and methods, after the `class` keyword.
+///
+/// After an error in one method, the parser skips ahead and tries the next.
+fn parse_class<'t>(tokens: &'t [Token], errors: &mut Vec<Error>) -> ParseResult<'t, Stmt> {
+    let (name, rest) =
+        take_if(tokens, identifier).ok_or_else(|| unexpected(tokens, "class name"))?;
+    let (superclass, rest) = match take_tok(rest, &Tok::Less) {
+        Some(after_less) => {
+            let ((name, place), rest) =
+                take_if(after_less, |t| identifier(t).map(|name| (name, t.place)))
+                    .ok_or_else(|| unexpected(after_less, "superclass name"))?;
+            let superclass = Expr::Variable {
+                name,
+                place,
+                depth: Cell::new(None),
+            };
+            (Some(superclass), rest)
+        }
+        None => (None, rest),
+    };
+    let open_place = match rest.first() {
+        Some(token) if token.tok == Tok::LeftBrace => token.place,
+        _ => return Err(unexpected(rest, "'{' before class body")),
+    };
+    let mut rest = &rest[1..];
+    let mut methods = Vec::new();
+    loop {
+        match rest.first() {
+            Some(token) if token.tok == Tok::RightBrace => {
+                let class = Stmt::Class {
+                    name,
+                    superclass,
+                    methods,
+                };
+                return Ok((class, &rest[1..]));
+            }
+            Some(_) => match parse_function(rest, errors) {
+                Ok((method, after)) => {
+                    methods.push(Rc::new(method));
+                    rest = after;
+                }
+                Err(err) => rest = recover_in_body(rest, err, errors)?,
+            },
+            None => {
+                return Err(Error {
+                    place: None,
+                    kind: ErrorKind::Unclosed {
+                        opener: "'{'",
+                        opener_place: open_place,
+                    },
+                })
+            }
+        }
+    }
+}
+
+/// Parse a function's name, parameters, and body.
+///
+///    function       → IDENTIFIER "(" parameters? ")" block ;
+///    parameters     → IDENTIFIER ( "," IDENTIFIER )* ;
+fn parse_function<'t>(
+    tokens: &'t [Token],
+    errors: &mut Vec<Error>,
+) -> ParseResult<'t, FunctionDecl> {
+    let (name, rest) =
+        take_if(tokens, identifier).ok_or_else(|| unexpected(tokens, "function name"))?;
+    let rest = expect_tok(rest, &Tok::LeftParen, "'(' after function name")?;
+    parse_parameters_and_body(name, rest, errors)
+}
+
+/// Parse a function's parameters and body, after the opening parenthesis.
+fn parse_parameters_and_body<'t>(
+    name: String,
+    tokens: &'t [Token],
+    errors: &mut Vec<Error>,
+) -> ParseResult<'t, FunctionDecl> {
+    let mut rest = tokens;
+    let mut params = Vec::new();
+    if !matches!(rest.first(), Some(token) if token.tok == Tok::RightParen) {
+        loop {
+            if params.len() == MAX_ARGUMENTS {
+                return Err(Error {
+                    place: rest.first().map(|t| t.place),
+                    kind: ErrorKind::TooMany { what: "parameters" },
+                });
+            }
+            let (param, after_param) =
+                take_if(rest, identifier).ok_or_else(|| unexpected(rest, "parameter name"))?;
+            params.push(param);
+            rest = after_param;
+            match take_tok(rest, &Tok::Comma) {
+                Some(after_comma) => rest = after_comma,
+                None => break,
+            }
+        }
+    }
+    let rest = expect_tok(rest, &Tok::RightParen, "')' after parameters")?;
+    if !matches!(rest.first(), Some(token) if token.tok == Tok::LeftBrace) {
+        return Err(unexpected(rest, "'{' before function body"));
+    }
+    let (body, rest) = parse_block(rest, errors)?;
+    Ok((FunctionDecl { name, params, body }, rest))
+}
+
+/// Parse one statement.
+///
+///    statement      → forStmt | ifStmt | printStmt | whileStmt | breakStmt
+///                     | continueStmt | returnStmt | block | exprStmt ;
+///    forStmt        → "for" "(" ( varDecl | exprStmt | ";" )
+///                     expression? ";" expression? ")" statement ;
+///    ifStmt         → "if" "(" expression ")" statement ( "else" statement )? ;
+///    printStmt      → "print" expression? ";" ;
+///    whileStmt      → "while" "(" expression ")" statement ;
+///    breakStmt      → "break" ";" ;
+///    continueStmt   → "continue" ";" ;
+///    returnStmt     → "return" expression? ";" ;
+///    exprStmt       → expression ";" ;
+///
+/// An `else` belongs to the nearest `if`. The semicolon after the last statement may be
+/// omitted. Whether `break`, `continue`, and `return` are inside a loop or function is
+/// checked later, by the resolver.
+fn parse_statement<'t>(tokens: &'t [Token], errors: &mut Vec<Error>) -> ParseResult<'t, Stmt> {
+    if let Some(rest) = take_tok(tokens, &Tok::For) {
+        parse_for(rest, errors)
+    } else if let Some(rest) = take_tok(tokens, &Tok::If) {
+        let rest = expect_tok(rest, &Tok::LeftParen, "'(' after 'if'")?;
+        let (cond, rest) = parse_expression(rest)?;
+        let rest = expect_tok(rest, &Tok::RightParen, "')' after if condition")?;
+        let (then_branch, rest) = parse_statement(rest, errors)?;
+        let (else_branch, rest) = match take_tok(rest, &Tok::Else) {
+            Some(rest) => {
+                let (else_branch, rest) = parse_statement(rest, errors)?;
+                (Some(Box::new(else_branch)), rest)
+            }
+            None => (None, rest),
+        };
+        Ok((
+            Stmt::If {
+                cond,
+                then_branch: Box::new(then_branch),
+                else_branch,
+            },
+            rest,
+        ))
+    } else if let Some(rest) = take_tok(tokens, &Tok::While) {
+        let rest = expect_tok(rest, &Tok::LeftParen, "'(' after 'while'")?;
+        let (cond, rest) = parse_expression(rest)?;
+        let rest = expect_tok(rest, &Tok::RightParen, "')' after while condition")?;
+        let (body, rest) = parse_statement(rest, errors)?;
+        Ok((
+            Stmt::While {
+                cond,
+                body: Box::new(body),
+                increment: None,
+            },
+            rest,
+        ))
+    } else if let Some((token, rest)) = tokens
+        .split_first()
+        .filter(|(t, _)| matches!(t.tok, Tok::Break | Tok::Continue))
+    {
+        let place = token.place;
+        let (stmt, expected) = match token.tok {
+            Tok::Break => (Stmt::Break { place }, "';' after 'break'"),
+            _ => (Stmt::Continue { place }, "';' after 'continue'"),
+        };
+        let rest = end_statement(rest, expected)?;
+        Ok((stmt, rest))
+    } else if let Some((token, rest)) = tokens.split_first().filter(|(t, _)| t.tok == Tok::Return) {
+        let place = token.place;
+        if let Some(rest) = take_tok(rest, &Tok::Semicolon) {
+            return Ok((Stmt::Return { place, value: None }, rest));
+        }
+        let (value, rest) = match rest.first() {
+            None => return Ok((Stmt::Return { place, value: None }, rest)),
+            Some(_) => parse_terminated_expression(rest, "';' after return value")?,
+        };
+        Ok((
+            Stmt::Return {
+                place,
+                value: Some(value),
+            },
+            rest,
+        ))
+    } else if matches!(tokens.first(), Some(token) if token.tok == Tok::LeftBrace) {
+        let (stmts, rest) = parse_block(tokens, errors)?;
+        Ok((Stmt::Block(stmts), rest))
+    } else if let Some((token, rest)) = tokens.split_first().filter(|(t, _)| t.tok == Tok::Print) {
+        let place = token.place;
+        // A bare `print` prints an empty line.
+        if let Some(rest) = take_tok(rest, &Tok::Semicolon) {
+            return Ok((Stmt::Print { place, value: None }, rest));
+        }
+        let (value, rest) = match rest.first() {
+            None => return Ok((Stmt::Print { place, value: None }, rest)),
+            Some(_) => parse_terminated_expression(rest, "';' after value")?,
+        };
+        Ok((
+            Stmt::Print {
+                place,
+                value: Some(value),
+            },
+            rest,
+        ))
+    } else {
+        let (expr, rest) = parse_terminated_expression(tokens, "';' after expression")?;
+        Ok((Stmt::Expression(expr), rest))
+    }
+}
+
+/// Parse the rest of a `for` loop, after the `for` keyword, desugaring it into a `while`
+/// loop.
+///
+/// `for (init; cond; incr) body` becomes `{ init; while (cond) body }`, with `incr` as the
+/// increment of the while loop, leaving out the parts that are omitted. A missing condition
+/// is always true.
+fn parse_for<'t>(tokens: &'t [Token], errors: &mut Vec<Error>) -> ParseResult<'t, Stmt> {
+    let rest = expect_tok(tokens, &Tok::LeftParen, "'(' after 'for'")?;
+    let (initializer, rest) = if let Some(rest) = take_tok(rest, &Tok::Semicolon) {
+        (None, rest)
+    } else if matches!(rest.first(), Some(token) if token.tok == Tok::Var) {
+        let (initializer, rest) = parse_declaration(rest, errors)?;
+        (Some(initializer), rest)
+    } else {
+        let (expr, rest) = parse_expression(rest)?;
+        let rest = expect_tok(rest, &Tok::Semicolon, "';' after loop initializer")?;
+        (Some(Stmt::Expression(expr)), rest)
+    };
+    let (cond, rest) = match rest.first() {
+        Some(token) if token.tok == Tok::Semicolon => (Expr::Literal(Value::Bool(true)), rest),
+        _ => parse_expression(rest)?,
+    };
+    let rest = expect_tok(rest, &Tok::Semicolon, "';' after loop condition")?;
+    let (increment, rest) = match rest.first() {
+        Some(token) if token.tok == Tok::RightParen => (None, rest),
+        _ => {
+            let (increment, rest) = parse_expression(rest)?;
+            (Some(increment), rest)
+        }
+    };
+    let rest = expect_tok(rest, &Tok::RightParen, "')' after for clauses")?;
+    let (body, rest) = parse_statement(rest, errors)?;
+
+    let mut stmt = Stmt::While {
+        cond,
+        body: Box::new(body),
+        increment,
+    };
+    if let Some(initializer) = initializer {
+        stmt = Stmt::Block(vec![initializer, stmt]);
+    }
+    Ok((stmt, rest))
+}
+
+/// Parse the statements in a block, starting with its opening brace.
+///
+///    block          → "{" declaration* "}" ;
+///
+/// After an error in one statement, the parser skips to the next and carries on, adding the
+/// error to `errors`. Only the statements that parsed are returned.
+fn parse_block<'t>(tokens: &'t [Token], errors: &mut Vec<Error>) -> ParseResult<'t, Vec<Stmt>> {
+    let open_place = tokens[0].place;
+    let mut rest = &tokens[1..];
+    let mut stmts = Vec::new();
+    loop {
+        match rest.first() {
+            Some(token) if token.tok == Tok::RightBrace => return Ok((stmts, &rest[1..])),
+            Some(_) => match parse_declaration(rest, errors) {
+                Ok((stmt, after)) => {
+                    stmts.push(stmt);
+                    rest = after;
+                }
+                Err(err) => rest = recover_in_body(rest, err, errors)?,
+            },
+            None => {
+                return Err(Error {
+                    place: None,
+                    kind: ErrorKind::Unclosed {
+                        opener: "'{'",
+                        opener_place: open_place,
+                    },
+                })
+            }
+        }
+    }
+}
+
+/// Parse an expression followed by a semicolon, which may be omitted at the end of the input.
+fn parse_terminated_expression<'t>(
+    tokens: &'t [Token],
+    expected_semicolon: &'static str,
+) -> ParseResult<'t, Expr> {
+    let (expr, rest) = parse_expression(tokens)?;
+    let rest = end_statement(rest, expected_semicolon)?;
+    Ok((expr, rest))
+}
+
+/// Consume the semicolon that ends a statement, which may be omitted at the end of the input.
+fn end_statement<'t>(
+    tokens: &'t [Token],
+    expected_semicolon: &'static str,
+) -> Result<&'t [Token], Error> {
+    if tokens.is_empty() {
+        Ok(tokens)
+    } else {
+        expect_tok(tokens, &Tok::Semicolon, expected_semicolon)
+    }
+}
+
+/// Skip tokens from the start of a statement that failed to parse, up to the start of
+/// what's probably the next statement: either after a semicolon, or at a keyword that
+/// starts a statement, or at a `}` that closes the enclosing block.
+///
+/// Blocks within the skipped tokens are skipped as a whole, so that their statements and
+/// closing brace aren't mistaken for the enclosing block's.
+///
+/// At least one token is always skipped, so that parsing makes progress.
+fn synchronize(tokens: &[Token]) -> &[Token] {
+    let mut depth = 0usize;
+    let mut rest = tokens;
+    while let Some((token, after)) = rest.split_first() {
+        rest = after;
+        match token.tok {
+            Tok::LeftBrace => depth += 1,
+            Tok::RightBrace => depth = depth.saturating_sub(1),
+            Tok::Semicolon if depth == 0 => break,
+            _ => (),
+        }
+        if depth > 0 {
+            continue;
+        }
+        if let Some(next) = rest.first() {
+            use Tok::*;
+            if matches!(
+                next.tok,
+                Class | Fun | Var | Const | For | If | While | Print | Return | RightBrace
+            ) {
+                break;
+            }
+        }
+    }
+    rest
+}
+
+/// Parse any expression.
+///
+///    expression     → comma ;
+fn parse_expression(tokens: &[Token]) -> ParseResult<'_, Expr> {
+    parse_comma(tokens)
+}
+
+/// Parse a sequence of expressions separated by commas, or anything of higher precedence.
+///
+///    comma          → assignment ( "," assignment )* ;
+///
+/// Places where commas separate items, such as function arguments, should parse each
+/// item with the rule above this one.
+fn parse_comma(tokens: &[Token]) -> ParseResult<'_, Expr> {
+    parse_left_associative(tokens, parse_assignment, |tok| {
+        (*tok == Tok::Comma).then_some(BinaryOp::Comma)
+    })
+}
+
+/// Parse an assignment, or anything of higher precedence.
+///
+///    assignment     → ( call "." )? IDENTIFIER "=" assignment | ternary ;
+///
+/// This is right-associative, so `a = b = c` assigns `c` to both. The target is parsed as
+/// an ordinary expression and then checked to be a variable or a property.
+fn parse_assignment(tokens: &[Token]) -> ParseResult<'_, Expr> {
+    let (target, rest) = parse_ternary(tokens)?;
+    let equal = match rest.first() {
+        Some(token) if token.tok == Tok::Equal => token,
+        _ => return Ok((target, rest)),
+    };
+    let (value, rest) = parse_assignment(&rest[1..])?;
+    match target {
+        Expr::Variable { name, place, depth } => Ok((
+            Expr::Assign {
+                name,
+                place,
+                depth,
+                value: Box::new(value),
+            },
+            rest,
+        )),
+        Expr::Get {
+            object,
+            name,
+            place,
+        } => Ok((
+            Expr::Set {
+                object,
+                name,
+                place,
+                value: Box::new(value),
+            },
+            rest,
+        )),
+        _ => Err(Error {
+            place: Some(equal.place),
+            kind: ErrorKind::InvalidAssignmentTarget,
+        }),
+    }
+}
+
+/// Parse a conditional expression, or anything of higher precedence.
+///
+///    ternary        → logic_or ( "?" expression ":" ternary )? ;
+///
+/// This is right-associative, so `a ? b : c ? d : e` means `a ? b : (c ? d : e)`.
+fn parse_ternary(tokens: &[Token]) -> ParseResult<'_, Expr> {
+    let (cond, rest) = parse_or(tokens)?;
+    let question = match rest.first() {
+        Some(token) if token.tok == Tok::Question => token,
+        _ => return Ok((cond, rest)),
+    };
+    let (then_expr, rest) = parse_expression(&rest[1..])?;
+    let rest = take_tok(rest, &Tok::Colon).ok_or_else(|| Error {
+        place: rest.first().map(|t| t.place),
+        kind: ErrorKind::Unmatched {
+            expected: "':'",
+            opener: "'?'",
+            opener_place: question.place,
+        },
+    })?;
+    let (else_expr, rest) = parse_ternary(rest)?;
+    Ok((
+        Expr::Ternary {
+            place: question.place,
+            cond: Box::new(cond),
+            then_expr: Box::new(then_expr),
+            else_expr: Box::new(else_expr),
+        },
+        rest,
+    ))
+}
+
+/// Parse a logical or, or anything of higher precedence.
+///
+///    logic_or       → logic_and ( "or" logic_and )* ;
+fn parse_or(tokens: &[Token]) -> ParseResult<'_, Expr> {
+    parse_left_associative(tokens, parse_and, |tok| {
+        (*tok == Tok::Or).then_some(LogicalOp::Or)
+    })
+}
+
+/// Parse a logical and, or anything of higher precedence.
+///
+///    logic_and      → equality ( "and" equality )* ;
+fn parse_and(tokens: &[Token]) -> ParseResult<'_, Expr> {
+    parse_left_associative(tokens, parse_equality, |tok| {
+        (*tok == Tok::And).then_some(LogicalOp::And)
+    })
+}
+
+/// Parse an equality test, or anything of higher precedence.
+///
+///    equality       → comparison ( ( "!=" | "==" ) comparison )* ;
+fn parse_equality(tokens: &[Token]) -> ParseResult<'_, Expr> {
+    parse_left_associative(tokens, parse_comparison, |tok| match tok {
+        Tok::BangEqual => Some(BinaryOp::NotEqual),
+        Tok::EqualEqual => Some(BinaryOp::EqualEqual),
+        _ => None,
+    })
+}
+
+/// Parse an ordering comparison, or anything of higher precedence.
+///
+///    comparison     → term ( ( ">" | ">=" | "<" | "<=" ) term )* ;
+fn parse_comparison(tokens: &[Token]) -> ParseResult<'_, Expr> {
+    parse_left_associative(tokens, parse_term, |tok| match tok {
+        Tok::Greater => Some(BinaryOp::GreaterThan),
+        Tok::GreaterEqual => Some(BinaryOp::GreaterEqual),
+        Tok::Less => Some(BinaryOp::LessThan),
+        Tok::LessEqual => Some(BinaryOp::LessEqual),
+        _ => None,
+    })
+}
+
+/// Parse addition or subtraction, or anything of higher precedence.
+///
+///    term           → factor ( ( "-" | "+" ) factor )* ;
+fn parse_term(tokens: &[Token]) -> ParseResult<'_, Expr> {
+    parse_left_associative(tokens, parse_factor, |tok| match tok {
+        Tok::Minus => Some(BinaryOp::Minus),
+        Tok::Plus => Some(BinaryOp::Plus),
+        _ => None,
+    })
+}
+
+/// Parse multiplication, division, or remainder, or anything of higher precedence.
+///
+///    factor         → unary ( ( "/" | "*" | "%" ) unary )* ;
+fn parse_factor(tokens: &[Token]) -> ParseResult<'_, Expr> {
+    parse_left_associative(tokens, parse_unary, |tok| match tok {
+        Tok::Slash => Some(BinaryOp::Divide),
+        Tok::Star => Some(BinaryOp::Multiply),
+        Tok::Percent => Some(BinaryOp::Modulo),
+        _ => None,
+    })
+}
+
+/// Parse a unary negation or logical not, or anything of higher precedence.
+///
+///    unary          → ( "!" | "-" ) unary | power ;
+fn parse_unary(tokens: &[Token]) -> ParseResult<'_, Expr> {
+    let unary_op = |t: &Token| match t.tok {
+        Tok::Bang => Some(UnaryOp::Not),
+        Tok::Minus => Some(UnaryOp::Negative),
+        _ => None,
+    };
+    if let Some(((op, place), rest)) = take_if(tokens, |t| unary_op(t).map(|op| (op, t.place))) {
+        let (expr, rest) = parse_unary(rest)?;
+        Ok((
+            Expr::Unary {
+                op,
+                place,
+                expr: Box::new(expr),
+            },
+            rest,
+        ))
+    } else {
+        parse_power(tokens)
+    }
+}
+
+/// Parse exponentiation, or anything of higher precedence.
+///
+///    power          → call ( "**" unary )? ;
+///
+/// This is right-associative, so `2 ** 3 ** 2` means `2 ** (3 ** 2)`. It binds tighter than
+/// a unary operator to its left, so `-2 ** 2` means `-(2 ** 2)`, but the exponent can
+/// itself be negated, as in `2 ** -1`.
+fn parse_power(tokens: &[Token]) -> ParseResult<'_, Expr> {
+    let (base, rest) = parse_call(tokens)?;
+    match rest.first() {
+        Some(token) if token.tok == Tok::StarStar => {
+            let (exponent, rest) = parse_unary(&rest[1..])?;
+            Ok((
+                Expr::Binary {
+                    op: BinaryOp::Power,
+                    place: token.place,
+                    left: Box::new(base),
+                    right: Box::new(exponent),
+                },
+                rest,
+            ))
+        }
+        _ => Ok((base, rest)),
+    }
+}
+
+/// The most arguments that can be passed in one call.
+const MAX_ARGUMENTS: usize = 255;
+
+/// Parse a function call or property access, or anything of higher precedence.
+///
+///    call           → primary ( "(" arguments? ")" | "." IDENTIFIER )* ;
+///    arguments      → assignment ( "," assignment )* ;
+///
+/// Calls and property accesses can be chained, as in `f()()` or `a.b().c`, each applying to
+/// whatever the previous one yielded.
+fn parse_call(tokens: &[Token]) -> ParseResult<'_, Expr> {
+    let (mut expr, mut rest) = parse_primary(tokens)?;
+    loop {
+        if let Some(after_dot) = take_tok(rest, &Tok::Dot) {
+            let ((name, place), after_name) =
+                take_if(after_dot, |t| identifier(t).map(|name| (name, t.place)))
+                    .ok_or_else(|| unexpected(after_dot, "property name after '.'"))?;
+            expr = Expr::Get {
+                object: Box::new(expr),
+                name,
+                place,
+            };
+            rest = after_name;
+            continue;
+        }
+        let after_paren = match take_tok(rest, &Tok::LeftParen) {
+            Some(after_paren) => after_paren,
+            None => break,
+        };
+        let paren = rest[0].place;
+        let mut args = Vec::new();
+        let mut after_args = after_paren;
+        if take_tok(after_args, &Tok::RightParen).is_none() {
+            loop {
+                if args.len() == MAX_ARGUMENTS {
+                    return Err(Error {
+                        place: after_args.first().map(|t| t.place),
+                        kind: ErrorKind::TooMany { what: "arguments" },
+                    });
+                }
+                let (arg, after_arg) = parse_assignment(after_args)?;
+                args.push(arg);
+                after_args = after_arg;
+                match take_tok(after_args, &Tok::Comma) {
+                    Some(after_comma) => after_args = after_comma,
+                    None => break,
+                }
+            }
+        }
+        rest = expect_tok(after_args, &Tok::RightParen, "')' after arguments")?;
+        expr = Expr::Call {
+            callee: Box::new(expr),
+            paren,
+            args,
+        };
+    }
+    Ok((expr, rest))
+}
+
+/// Parse a literal, a variable name, or a parenthesized expression.
+///
+///    primary        → literal | "this" | "super" "." IDENTIFIER | IDENTIFIER
+///                     | "(" expression ")" | lambda ;
+///    lambda         → "fun" "(" parameters? ")" block ;
+///
+/// Expressions can only fail with one error, so if there are errors in the body of an
+/// anonymous function, only the first is returned.
+fn parse_primary(tokens: &[Token]) -> ParseResult<'_, Expr> {
+    if let Some(rest) = take_tok(tokens, &Tok::LeftParen) {
+        let (expr, rest) = parse_expression(rest)?;
+        let rest = expect_tok(rest, &Tok::RightParen, "')' after expression")?;
+        Ok((
+            Expr::Grouping {
+                expr: Box::new(expr),
+            },
+            rest,
+        ))
+    } else if let Some(rest) = take_tok(tokens, &Tok::Super) {
+        let rest = expect_tok(rest, &Tok::Dot, "'.' after 'super'")?;
+        let (method, rest) =
+            take_if(rest, identifier).ok_or_else(|| unexpected(rest, "superclass method name"))?;
+        Ok((
+            Expr::Super {
+                place: tokens[0].place,
+                method,
+                depth: Cell::new(None),
+            },
+            rest,
+        ))
+    } else if let Some(rest) = take_tok(tokens, &Tok::Fun) {
+        let rest = expect_tok(rest, &Tok::LeftParen, "'(' after 'fun'")?;
+        let mut errors = Vec::new();
+        let (decl, rest) = parse_parameters_and_body(String::new(), rest, &mut errors)?;
+        if !errors.is_empty() {
+            return Err(errors.remove(0));
+        }
+        Ok((Expr::Function(Rc::new(decl)), rest))
+    } else if let Some(rest) = take_tok(tokens, &Tok::This) {
+        Ok((
+            Expr::This {
+                place: tokens[0].place,
+                depth: Cell::new(None),
+            },
+            rest,
+        ))
+    } else if let Some(((name, place), rest)) =
+        take_if(tokens, |t| identifier(t).map(|name| (name, t.place)))
+    {
+        Ok((
+            Expr::Variable {
+                name,
+                place,
+                depth: Cell::new(None),
+            },
+            rest,
+        ))
+    } else {
+        parse_literal(tokens)
+    }
+}
+
 /// Parse a literal value: string, number, bool, or nil.
-fn parse_literal(tokens: &[Token]) -> Result<(Expr, &[Token])> {
+fn parse_literal(tokens: &[Token]) -> ParseResult<'_, Expr> {
     take_if(tokens, |t| Value::from_literal_token(t).map(Expr::Literal))
-        .ok_or(anyhow!("not a literal"))
+        .ok_or_else(|| unexpected(tokens, "expression"))
+}
+
+/// An operator that combines two operands into an expression.
+trait Operator {
+    fn make_expr(self, place: Place, left: Expr, right: Expr) -> Expr;
+}
+
+impl Operator for BinaryOp {
+    fn make_expr(self, place: Place, left: Expr, right: Expr) -> Expr {
+        Expr::Binary {
+            op: self,
+            place,
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
 }
 
-///// Parse a unary expression:
-/////
-///// unary          → ( "-" | "!" ) expression ;
-//fn parse_unary(_tokens: &[Token]) -> Result<(Expr, &[Token])> {
-//    todo!()
-//}
+impl Operator for LogicalOp {
+    fn make_expr(self, place: Place, left: Expr, right: Expr) -> Expr {
+        Expr::Logical {
+            op: self,
+            place,
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+}
 
-/// Parse any expression
+/// Parse a sequence of one or more operands separated by binary operators, all at the
+/// same precedence level, grouping them to the left.
 ///
-///    expression     → literal
-///                   | unary
-///                   | binary
-///                   | grouping
+/// `operand_fn` parses the operands, and `op_fn` recognizes operator tokens at this level.
+fn parse_left_associative<Op: Operator>(
+    tokens: &[Token],
+    operand_fn: fn(&[Token]) -> ParseResult<'_, Expr>,
+    op_fn: fn(&Tok) -> Option<Op>,
+) -> ParseResult<'_, Expr> {
+    let (mut left, mut rest) = operand_fn(tokens)?;
+    while let Some(((op, place), after_op)) =
+        take_if(rest, |t| op_fn(&t.tok).map(|op| (op, t.place)))
+    {
+        let (right, after_right) = operand_fn(after_op)?;
+        left = op.make_expr(place, left, right);
+        rest = after_right;
+    }
+    Ok((left, rest))
+}
 
-pub fn parse_expr(tokens: &[Token]) -> Result<(Expr, &[Token])> {
-    let (expr, rest) = parse_literal(tokens)?;
-    if let Some(next_token) = rest.first() {
-        return Err(anyhow!(
-            "unexpected tokens after literal {:?}: {:?}",
-            expr,
-            next_token
-        ));
+/// Construct an error saying that the first of `tokens` is not the `expected` construct,
+/// or that the input ended if there are no more tokens.
+fn unexpected(tokens: &[Token], expected: &'static str) -> Error {
+    match tokens.first() {
+        Some(token) => Error {
+            place: Some(token.place),
+            kind: ErrorKind::Unexpected {
+                expected,
+                found: token.lexeme.clone(),
+            },
+        },
+        None => Error {
+            place: None,
+            kind: ErrorKind::UnexpectedEnd { expected },
+        },
+    }
+}
+
+/// If the next token is `tok`, consume it and return the remaining tokens.
+fn take_tok<'t>(tokens: &'t [Token], tok: &Tok) -> Option<&'t [Token]> {
+    match tokens.first() {
+        Some(token) if token.tok == *tok => Some(&tokens[1..]),
+        _ => None,
+    }
+}
+
+/// Consume the next token, which must be `tok`, and return the remaining tokens.
+///
+/// If the next token is anything else, return an error describing what was `expected`.
+fn expect_tok<'t>(
+    tokens: &'t [Token],
+    tok: &Tok,
+    expected: &'static str,
+) -> Result<&'t [Token], Error> {
+    take_tok(tokens, tok).ok_or_else(|| unexpected(tokens, expected))
+}
+
+/// The name in an identifier token.
+fn identifier(token: &Token) -> Option<String> {
+    match &token.tok {
+        Tok::Identifier(name) => Some(name.clone()),
+        _ => None,
     }
-    Ok((expr, rest))
 }
 
 /// Parse and consume one element if the function matches it.
-fn take_if<T>(tokens: &[Token], match_fn: fn(&Token) -> Option<T>) -> Option<(T, &[Token])> {
+fn take_if<T, F>(tokens: &[Token], match_fn: F) -> Option<(T, &[Token])>
+where
+    F: Fn(&Token) -> Option<T>,
+{
     tokens.first().and_then(match_fn).map(|t| (t, &tokens[1..]))
 }
 
@@ -67,16 +1201,17 @@ mod test {
 
     /// Parse a string, expecting that there are no errors and nothing
     /// remaining unparsed.
-    fn parse_exactly(source: &str, parse_fn: fn(&[Token]) -> Result<(Expr, &[Token])>) -> Expr {
-        let tokens = lex(source)
-            .into_iter()
-            .map(Result::unwrap)
-            .collect::<Vec<Token>>();
+    fn parse_exactly(source: &str, parse_fn: fn(&[Token]) -> ParseResult<'_, Expr>) -> Expr {
+        let tokens = lex_tokens(source);
         let (expr, remaining) = parse_fn(&tokens).unwrap();
         assert_eq!(remaining.len(), 0);
         expr
     }
 
+    fn lex_tokens(source: &str) -> Vec<Token> {
+        lex(source).into_iter().map(Result::unwrap).collect()
+    }
+
     #[test]
     fn parse_literal_number() {
         assert_eq!(
@@ -116,4 +1251,1277 @@ mod test {
             Expr::Literal(Value::Bool(true))
         );
     }
+
+    #[test]
+    fn leading_operator_is_located_error() {
+        let err = parse_expression(&lex_tokens("+1")).unwrap_err();
+        assert_eq!(
+            err,
+            Error {
+                place: Some(Place::new(1, 1, 0)),
+                kind: ErrorKind::Unexpected {
+                    expected: "expression",
+                    found: "+".to_owned(),
+                },
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "[line 1 column 1] Error: expected expression, found '+'. [E005]"
+        );
+    }
+
+    #[test]
+    fn incomplete_programs() {
+        for (source, incomplete) in [
+            ("fun f() {", true),
+            ("fun f() {\n  return 1;", true),
+            ("print 1 +", true),
+            ("if (a", true),
+            ("print (1;", false),
+            ("fun f() { ) }", false),
+            ("fun f() {}", false),
+            ("1 + 2", false),
+            ("", false),
+        ] {
+            assert_eq!(
+                is_incomplete(&lex_tokens(source)),
+                incomplete,
+                "{:?}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn empty_input_is_error_at_end() {
+        assert_eq!(
+            parse_expression(&[]).unwrap_err().to_string(),
+            "[end of input] Error: expected expression, found end of input. [E006]"
+        );
+    }
+
+    fn number(n: f64) -> Expr {
+        Expr::Literal(Value::Number(n))
+    }
+
+    /// The place of a token at `column` on the first line, in tests of one-line sources.
+    fn at(column: usize) -> Place {
+        Place::new(1, column, column - 1)
+    }
+
+    fn binary(op: BinaryOp, column: usize, left: Expr, right: Expr) -> Expr {
+        Expr::Binary {
+            op,
+            place: at(column),
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+
+    #[test]
+    fn multiplication_binds_tighter_than_addition() {
+        use BinaryOp::*;
+        assert_eq!(
+            parse_exactly("1 + 2 * 3 - 4", parse_expression),
+            binary(
+                Minus,
+                11,
+                binary(
+                    Plus,
+                    3,
+                    number(1.0),
+                    binary(Multiply, 7, number(2.0), number(3.0))
+                ),
+                number(4.0)
+            )
+        );
+    }
+
+    #[test]
+    fn subtraction_is_left_associative() {
+        use BinaryOp::*;
+        assert_eq!(
+            parse_exactly("1 - 2 - 3", parse_expression),
+            binary(
+                Minus,
+                7,
+                binary(Minus, 3, number(1.0), number(2.0)),
+                number(3.0)
+            )
+        );
+        assert_eq!(
+            parse_exactly("8 / 4 * 2", parse_expression),
+            binary(
+                Multiply,
+                7,
+                binary(Divide, 3, number(8.0), number(4.0)),
+                number(2.0)
+            )
+        );
+        assert_eq!(
+            parse_exactly("1 + 7 % 3 * 2", parse_expression),
+            binary(
+                Plus,
+                3,
+                number(1.0),
+                binary(
+                    Multiply,
+                    11,
+                    binary(Modulo, 7, number(7.0), number(3.0)),
+                    number(2.0)
+                )
+            )
+        );
+    }
+
+    #[test]
+    fn comparison_binds_tighter_than_equality() {
+        use BinaryOp::*;
+        assert_eq!(
+            parse_exactly("1 < 2 == 3 >= 4 + 5", parse_expression),
+            binary(
+                EqualEqual,
+                7,
+                binary(LessThan, 3, number(1.0), number(2.0)),
+                binary(
+                    GreaterEqual,
+                    12,
+                    number(3.0),
+                    binary(Plus, 17, number(4.0), number(5.0))
+                )
+            )
+        );
+    }
+
+    #[test]
+    fn grouping_overrides_precedence() {
+        use BinaryOp::*;
+        assert_eq!(
+            parse_exactly("(1 + 2) * 3", parse_expression),
+            binary(
+                Multiply,
+                9,
+                Expr::Grouping {
+                    expr: Box::new(binary(Plus, 4, number(1.0), number(2.0)))
+                },
+                number(3.0)
+            )
+        );
+    }
+
+    #[test]
+    fn unclosed_grouping_is_error() {
+        assert_eq!(
+            parse_expression(&lex_tokens("(1 + 2")).unwrap_err().kind,
+            ErrorKind::UnexpectedEnd {
+                expected: "')' after expression"
+            }
+        );
+    }
+
+    #[test]
+    fn missing_right_operand_is_error() {
+        assert_eq!(
+            parse_expression(&lex_tokens("1 + * 2"))
+                .unwrap_err()
+                .to_string(),
+            "[line 1 column 5] Error: expected expression, found '*'. [E005]"
+        );
+    }
+
+    #[test]
+    fn program_of_several_expressions() {
+        let (stmts, errors) = parse_program(&lex_tokens("1; (2);\n3"));
+        assert_eq!(errors, []);
+        assert_eq!(
+            stmts,
+            [
+                Stmt::Expression(number(1.0)),
+                Stmt::Expression(Expr::Grouping {
+                    expr: Box::new(number(2.0))
+                }),
+                Stmt::Expression(number(3.0))
+            ]
+        );
+    }
+
+    #[test]
+    fn print_statement() {
+        let (stmts, errors) = parse_program(&lex_tokens("print 1 + 2; print \"x\""));
+        assert_eq!(errors, []);
+        assert_eq!(
+            stmts,
+            [
+                Stmt::Print {
+                    place: at(1),
+                    value: Some(binary(BinaryOp::Plus, 9, number(1.0), number(2.0)))
+                },
+                Stmt::Print {
+                    place: at(14),
+                    value: Some(Expr::Literal(Value::from("x")))
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn var_declaration() {
+        let (stmts, errors) = parse_program(&lex_tokens("var a = 1; var b; print a;"));
+        assert_eq!(errors, []);
+        assert_eq!(
+            stmts,
+            [
+                Stmt::Var {
+                    name: "a".to_owned(),
+                    place: at(5),
+                    initializer: Some(number(1.0)),
+                    constant: false,
+                },
+                Stmt::Var {
+                    name: "b".to_owned(),
+                    place: at(16),
+                    initializer: None,
+                    constant: false,
+                },
+                Stmt::Print {
+                    place: at(19),
+                    value: Some(variable("a", 25))
+                }
+            ]
+        );
+    }
+
+    fn variable(name: &str, column: usize) -> Expr {
+        Expr::Variable {
+            name: name.to_owned(),
+            place: at(column),
+            depth: Cell::new(None),
+        }
+    }
+
+    fn assign(name: &str, column: usize, value: Expr) -> Expr {
+        Expr::Assign {
+            name: name.to_owned(),
+            place: at(column),
+            depth: Cell::new(None),
+            value: Box::new(value),
+        }
+    }
+
+    #[test]
+    fn assignment_is_right_associative() {
+        assert_eq!(
+            parse_exactly("a = b = 1 + 2", parse_expression),
+            assign(
+                "a",
+                1,
+                assign("b", 5, binary(BinaryOp::Plus, 11, number(1.0), number(2.0)))
+            )
+        );
+    }
+
+    #[test]
+    fn assignment_binds_tighter_than_comma() {
+        assert_eq!(
+            parse_exactly("a = 1, b", parse_expression),
+            binary(
+                BinaryOp::Comma,
+                6,
+                assign("a", 1, number(1.0)),
+                variable("b", 8)
+            )
+        );
+    }
+
+    #[test]
+    fn invalid_assignment_target_is_error() {
+        for (source, expected) in [
+            (
+                "1 = 2",
+                "[line 1 column 3] Error: invalid assignment target. [E008]",
+            ),
+            (
+                "a + b = c",
+                "[line 1 column 7] Error: invalid assignment target. [E008]",
+            ),
+        ] {
+            assert_eq!(
+                parse_expression(&lex_tokens(source))
+                    .unwrap_err()
+                    .to_string(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn nested_blocks() {
+        let (stmts, errors) = parse_program(&lex_tokens("{ var a = 1; { print a; } {} }"));
+        assert_eq!(errors, []);
+        assert_eq!(
+            stmts,
+            [Stmt::Block(vec![
+                Stmt::Var {
+                    name: "a".to_owned(),
+                    place: at(7),
+                    initializer: Some(number(1.0)),
+                    constant: false,
+                },
+                Stmt::Block(vec![Stmt::Print {
+                    place: at(16),
+                    value: Some(variable("a", 22))
+                }]),
+                Stmt::Block(vec![]),
+            ])]
+        );
+    }
+
+    #[test]
+    fn unclosed_block_is_error() {
+        let (_stmts, errors) = parse_program(&lex_tokens("{ print 1;\n"));
+        assert_eq!(
+            errors
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<String>>(),
+            ["[end of input] Error: unclosed '{' opened at line 1 column 1. [E017]"]
+        );
+    }
+
+    #[test]
+    fn unclosed_function_body_is_error() {
+        let (_stmts, errors) = parse_program(&lex_tokens("fun f() { print 1;"));
+        assert_eq!(errors.len(), 1);
+        let error = &errors[0];
+        assert_eq!(error.place, None);
+        assert_eq!(
+            error.kind,
+            ErrorKind::Unclosed {
+                opener: "'{'",
+                opener_place: Place::new(1, 9, 8),
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "[end of input] Error: unclosed '{' opened at line 1 column 9. [E017]"
+        );
+    }
+
+    #[test]
+    fn dangling_else_belongs_to_nearest_if() {
+        let (stmts, errors) = parse_program(&lex_tokens("if (a) if (b) print 1; else print 2;"));
+        assert_eq!(errors, []);
+        assert_eq!(
+            stmts,
+            [Stmt::If {
+                cond: variable("a", 5),
+                then_branch: Box::new(Stmt::If {
+                    cond: variable("b", 12),
+                    then_branch: Box::new(Stmt::Print {
+                        place: at(15),
+                        value: Some(number(1.0))
+                    }),
+                    else_branch: Some(Box::new(Stmt::Print {
+                        place: at(29),
+                        value: Some(number(2.0))
+                    })),
+                }),
+                else_branch: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn if_without_parens_is_error() {
+        let (_stmts, errors) = parse_program(&lex_tokens("if true print 1;"));
+        assert_eq!(
+            errors[0].to_string(),
+            "[line 1 column 4] Error: expected '(' after 'if', found 'true'. [E005]"
+        );
+    }
+
+    #[test]
+    fn while_statement() {
+        let (stmts, errors) = parse_program(&lex_tokens("while (a) { a = nil; }"));
+        assert_eq!(errors, []);
+        assert_eq!(
+            stmts,
+            [Stmt::While {
+                cond: variable("a", 8),
+                body: Box::new(Stmt::Block(vec![Stmt::Expression(assign(
+                    "a",
+                    13,
+                    Expr::Literal(Value::Nil)
+                ))])),
+                increment: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn for_loop_desugars_to_while() {
+        let (stmts, errors) =
+            parse_program(&lex_tokens("for (var i = 0; i < 3; i = i + 1) print i;"));
+        assert_eq!(errors, []);
+        assert_eq!(
+            stmts,
+            [Stmt::Block(vec![
+                Stmt::Var {
+                    name: "i".to_owned(),
+                    place: at(10),
+                    initializer: Some(number(0.0)),
+                    constant: false,
+                },
+                Stmt::While {
+                    cond: binary(BinaryOp::LessThan, 19, variable("i", 17), number(3.0)),
+                    body: Box::new(Stmt::Print {
+                        place: at(35),
+                        value: Some(variable("i", 41))
+                    }),
+                    increment: Some(assign(
+                        "i",
+                        24,
+                        binary(BinaryOp::Plus, 30, variable("i", 28), number(1.0))
+                    )),
+                },
+            ])]
+        );
+    }
+
+    #[test]
+    fn for_loop_clauses_may_be_omitted() {
+        let (stmts, errors) = parse_program(&lex_tokens("for (;;) print 1;"));
+        assert_eq!(errors, []);
+        assert_eq!(
+            stmts,
+            [Stmt::While {
+                cond: boolean(true),
+                body: Box::new(Stmt::Print {
+                    place: at(10),
+                    value: Some(number(1.0))
+                }),
+                increment: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn break_and_continue_in_loops() {
+        let (stmts, errors) =
+            parse_program(&lex_tokens("while (true) { if (a) break; continue; }"));
+        assert_eq!(errors, []);
+        assert_eq!(
+            stmts,
+            [Stmt::While {
+                cond: boolean(true),
+                body: Box::new(Stmt::Block(vec![
+                    Stmt::If {
+                        cond: variable("a", 20),
+                        then_branch: Box::new(Stmt::Break { place: at(23) }),
+                        else_branch: None,
+                    },
+                    Stmt::Continue { place: at(30) },
+                ])),
+                increment: None,
+            }]
+        );
+    }
+
+    fn call(callee: Expr, column: usize, args: Vec<Expr>) -> Expr {
+        Expr::Call {
+            callee: Box::new(callee),
+            paren: at(column),
+            args,
+        }
+    }
+
+    #[test]
+    fn call_with_no_arguments() {
+        assert_eq!(
+            parse_exactly("f()", parse_expression),
+            call(variable("f", 1), 2, vec![])
+        );
+    }
+
+    #[test]
+    fn call_with_one_argument() {
+        assert_eq!(
+            parse_exactly("f(1 + 2)", parse_expression),
+            call(
+                variable("f", 1),
+                2,
+                vec![binary(BinaryOp::Plus, 5, number(1.0), number(2.0))]
+            )
+        );
+    }
+
+    #[test]
+    fn call_with_several_arguments() {
+        assert_eq!(
+            parse_exactly("f(a, b = 2, nil)", parse_expression),
+            call(
+                variable("f", 1),
+                2,
+                vec![
+                    variable("a", 3),
+                    assign("b", 6, number(2.0)),
+                    Expr::Literal(Value::Nil)
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn chained_calls() {
+        assert_eq!(
+            parse_exactly("f(1)()", parse_expression),
+            call(call(variable("f", 1), 2, vec![number(1.0)]), 5, vec![])
+        );
+        assert_eq!(
+            parse_exactly("-f()", parse_expression),
+            unary(UnaryOp::Negative, 1, call(variable("f", 2), 3, vec![]))
+        );
+    }
+
+    fn get(object: Expr, name: &str, column: usize) -> Expr {
+        Expr::Get {
+            object: Box::new(object),
+            name: name.to_owned(),
+            place: at(column),
+        }
+    }
+
+    #[test]
+    fn property_get() {
+        assert_eq!(
+            parse_exactly("a.b", parse_expression),
+            get(variable("a", 1), "b", 3)
+        );
+    }
+
+    #[test]
+    fn chained_property_get_and_call() {
+        assert_eq!(
+            parse_exactly("a.b.c", parse_expression),
+            get(get(variable("a", 1), "b", 3), "c", 5)
+        );
+        assert_eq!(
+            parse_exactly("a.f(1).c", parse_expression),
+            get(
+                call(get(variable("a", 1), "f", 3), 4, vec![number(1.0)]),
+                "c",
+                8
+            )
+        );
+    }
+
+    #[test]
+    fn property_set() {
+        assert_eq!(
+            parse_exactly("a.b.c = 1", parse_expression),
+            Expr::Set {
+                object: Box::new(get(variable("a", 1), "b", 3)),
+                name: "c".to_owned(),
+                place: at(5),
+                value: Box::new(number(1.0)),
+            }
+        );
+    }
+
+    #[test]
+    fn this_property() {
+        assert_eq!(
+            parse_exactly("this.x", parse_expression),
+            get(
+                Expr::This {
+                    place: at(1),
+                    depth: Cell::new(None)
+                },
+                "x",
+                6
+            )
+        );
+    }
+
+    #[test]
+    fn missing_property_name_is_error() {
+        assert_eq!(
+            parse_expression(&lex_tokens("a.1"))
+                .unwrap_err()
+                .to_string(),
+            "[line 1 column 3] Error: expected property name after '.', found '1'. [E005]"
+        );
+    }
+
+    #[test]
+    fn too_many_arguments_is_error() {
+        let source = format!("f({})", vec!["1"; 256].join(", "));
+        let err = parse_expression(&lex_tokens(&source)).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::TooMany { what: "arguments" });
+        assert_eq!(err.place, Some(at(3 + 255 * 3)));
+        let source = format!("f({})", vec!["1"; 255].join(", "));
+        assert!(parse_expression(&lex_tokens(&source)).is_ok());
+    }
+
+    #[test]
+    fn unclosed_call_is_error() {
+        assert_eq!(
+            parse_expression(&lex_tokens("f(1 2"))
+                .unwrap_err()
+                .to_string(),
+            "[line 1 column 5] Error: expected ')' after arguments, found '2'. [E005]"
+        );
+    }
+
+    #[test]
+    fn function_declaration() {
+        let (stmts, errors) = parse_program(&lex_tokens("fun add(a, b) { print a + b; }"));
+        assert_eq!(errors, []);
+        assert_eq!(
+            stmts,
+            [Stmt::Function(Rc::new(FunctionDecl {
+                name: "add".to_owned(),
+                params: vec!["a".to_owned(), "b".to_owned()],
+                body: vec![Stmt::Print {
+                    place: at(17),
+                    value: Some(binary(
+                        BinaryOp::Plus,
+                        25,
+                        variable("a", 23),
+                        variable("b", 27)
+                    ))
+                }],
+            }))]
+        );
+    }
+
+    #[test]
+    fn function_without_parameters() {
+        let (stmts, errors) = parse_program(&lex_tokens("fun f() {}"));
+        assert_eq!(errors, []);
+        assert_eq!(
+            stmts,
+            [Stmt::Function(Rc::new(FunctionDecl {
+                name: "f".to_owned(),
+                params: vec![],
+                body: vec![],
+            }))]
+        );
+    }
+
+    #[test]
+    fn anonymous_function_expression() {
+        let (stmts, errors) = parse_program(&lex_tokens("var f = fun (x) { return x; };"));
+        assert_eq!(errors, []);
+        assert_eq!(
+            stmts,
+            [Stmt::Var {
+                name: "f".to_owned(),
+                place: at(5),
+                initializer: Some(Expr::Function(Rc::new(FunctionDecl {
+                    name: String::new(),
+                    params: vec!["x".to_owned()],
+                    body: vec![Stmt::Return {
+                        place: at(19),
+                        value: Some(variable("x", 26)),
+                    }],
+                }))),
+                constant: false,
+            }]
+        );
+    }
+
+    #[test]
+    fn anonymous_function_statement_is_an_expression() {
+        let (stmts, errors) = parse_program(&lex_tokens("fun () {}();"));
+        assert_eq!(errors, []);
+        assert!(matches!(&stmts[..], [Stmt::Expression(Expr::Call { .. })]));
+    }
+
+    #[test]
+    fn anonymous_function_needs_parenthesis() {
+        let (_, errors) = parse_program(&lex_tokens("var f = fun g() {};"));
+        assert_eq!(
+            errors[0].to_string(),
+            "[line 1 column 13] Error: expected '(' after 'fun', found 'g'. [E005]"
+        );
+    }
+
+    #[test]
+    fn class_declaration() {
+        let (stmts, errors) = parse_program(&lex_tokens("class A { f() {} g(x) { print x; } }"));
+        assert_eq!(errors, []);
+        assert_eq!(
+            stmts,
+            [Stmt::Class {
+                name: "A".to_owned(),
+                superclass: None,
+                methods: vec![
+                    Rc::new(FunctionDecl {
+                        name: "f".to_owned(),
+                        params: vec![],
+                        body: vec![],
+                    }),
+                    Rc::new(FunctionDecl {
+                        name: "g".to_owned(),
+                        params: vec!["x".to_owned()],
+                        body: vec![Stmt::Print {
+                            place: at(25),
+                            value: Some(variable("x", 31))
+                        }],
+                    }),
+                ],
+            }]
+        );
+    }
+
+    #[test]
+    fn subclass_declaration() {
+        let (stmts, errors) = parse_program(&lex_tokens("class B < A { f() { super.f(); } }"));
+        assert_eq!(errors, []);
+        assert_eq!(
+            stmts,
+            [Stmt::Class {
+                name: "B".to_owned(),
+                superclass: Some(variable("A", 11)),
+                methods: vec![Rc::new(FunctionDecl {
+                    name: "f".to_owned(),
+                    params: vec![],
+                    body: vec![Stmt::Expression(call(
+                        Expr::Super {
+                            place: at(21),
+                            method: "f".to_owned(),
+                            depth: Cell::new(None),
+                        },
+                        28,
+                        vec![]
+                    ))],
+                })],
+            }]
+        );
+    }
+
+    #[test]
+    fn super_without_method_is_error() {
+        assert_eq!(
+            parse_expression(&lex_tokens("super"))
+                .unwrap_err()
+                .to_string(),
+            "[end of input] Error: expected '.' after 'super', found end of input. [E006]"
+        );
+    }
+
+    #[test]
+    fn unclosed_class_is_error() {
+        let (_stmts, errors) = parse_program(&lex_tokens("class A { f() {}"));
+        assert_eq!(
+            errors[0].to_string(),
+            "[end of input] Error: unclosed '{' opened at line 1 column 9. [E017]"
+        );
+    }
+
+    #[test]
+    fn too_many_parameters_is_error() {
+        let params: Vec<String> = (0..256).map(|i| format!("p{}", i)).collect();
+        let (_stmts, errors) =
+            parse_program(&lex_tokens(&format!("fun f({}) {{}}", params.join(", "))));
+        assert_eq!(errors[0].kind, ErrorKind::TooMany { what: "parameters" });
+    }
+
+    #[test]
+    fn return_statements() {
+        let (stmts, errors) = parse_program(&lex_tokens("fun f() { if (a) return; return 1; }"));
+        assert_eq!(errors, []);
+        assert_eq!(
+            stmts,
+            [Stmt::Function(Rc::new(FunctionDecl {
+                name: "f".to_owned(),
+                params: vec![],
+                body: vec![
+                    Stmt::If {
+                        cond: variable("a", 15),
+                        then_branch: Box::new(Stmt::Return {
+                            place: at(18),
+                            value: None
+                        }),
+                        else_branch: None,
+                    },
+                    Stmt::Return {
+                        place: at(26),
+                        value: Some(number(1.0))
+                    },
+                ],
+            }))]
+        );
+    }
+
+    #[test]
+    fn print_without_value() {
+        let (stmts, errors) = parse_program(&lex_tokens("print; print"));
+        assert_eq!(errors, []);
+        assert_eq!(
+            stmts,
+            [
+                Stmt::Print {
+                    place: at(1),
+                    value: None
+                },
+                Stmt::Print {
+                    place: at(8),
+                    value: None
+                }
+            ]
+        );
+        assert_eq!(stmts[0].tree(), "Print\n");
+    }
+
+    #[test]
+    fn misplaced_jumps_parse_normally() {
+        // Whether these are allowed here is up to the resolver.
+        let (stmts, errors) = parse_program(&lex_tokens("fun f() { break; }\nreturn 1;"));
+        assert_eq!(errors, []);
+        assert_eq!(stmts.len(), 2);
+    }
+
+    #[test]
+    fn var_without_name_is_error() {
+        let (_stmts, errors) = parse_program(&lex_tokens("var 1 = 2;"));
+        assert_eq!(
+            errors
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<String>>(),
+            ["[line 1 column 5] Error: expected variable name, found '1'. [E005]"]
+        );
+    }
+
+    #[test]
+    fn const_declaration() {
+        let (stmts, errors) = parse_program(&lex_tokens("const c = 1;"));
+        assert_eq!(errors, []);
+        assert_eq!(stmts[0].tree(), "Const c\n  Literal 1\n");
+    }
+
+    #[test]
+    fn math_chains_make_chain_expressions() {
+        let options = ParseOptions { math_chains: true };
+        let (stmts, errors) =
+            parse_program_with_options(&lex_tokens("1 < 2 <= 3 > 0; (1 < 2) < 3;"), options);
+        assert_eq!(errors, []);
+        assert_eq!(
+            stmts[0].tree(),
+            "Expression\n  Chain < <= >\n    Literal 1\n    Literal 2\n    Literal 3\n    Literal 0\n"
+        );
+        // Parentheses stop a chain.
+        assert_eq!(
+            stmts[1].tree(),
+            "Expression\n  Binary <\n    Grouping\n      Binary <\n        Literal 1\n        Literal 2\n    Literal 3\n"
+        );
+    }
+
+    #[test]
+    fn chained_comparisons_are_left_associative_by_default() {
+        let (stmts, errors) = parse_program(&lex_tokens("1 < 2 < 3;"));
+        assert_eq!(errors, []);
+        assert_eq!(
+            stmts[0].tree(),
+            "Expression\n  Binary <\n    Binary <\n      Literal 1\n      Literal 2\n    Literal 3\n"
+        );
+    }
+
+    #[test]
+    fn math_chains_inside_functions() {
+        let options = ParseOptions { math_chains: true };
+        let (stmts, errors) = parse_program_with_options(
+            &lex_tokens("fun f(x) { return 0 < x < fun () { return 1 < 2 < 3; }; }"),
+            options,
+        );
+        assert_eq!(errors, []);
+        let tree = stmts[0].tree();
+        assert_eq!(tree.matches("Chain < <").count(), 2, "{}", tree);
+    }
+
+    #[test]
+    fn const_without_value_is_error() {
+        assert_eq!(
+            error_strings("const c;"),
+            ["[line 1 column 8] Error: expected '=' after constant name, found ';'. [E005]"]
+        );
+    }
+
+    #[test]
+    fn print_without_semicolon_is_error() {
+        let (stmts, errors) = parse_program(&lex_tokens("print 1 print 2;"));
+        assert_eq!(stmts, []);
+        assert_eq!(
+            errors
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<String>>(),
+            ["[line 1 column 9] Error: expected ';' after value, found 'print'. [E005]"]
+        );
+    }
+
+    #[test]
+    fn missing_semicolon_is_error() {
+        let (stmts, errors) = parse_program(&lex_tokens("1 2"));
+        assert!(stmts.is_empty());
+        assert_eq!(
+            errors
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<String>>(),
+            ["[line 1 column 3] Error: expected ';' after expression, found '2'. [E005]"]
+        );
+    }
+
+    #[test]
+    fn program_reports_errors_from_several_statements() {
+        let (stmts, errors) = parse_program(&lex_tokens("+ 1;\n2;\n3 * ;\n4"));
+        assert_eq!(
+            stmts,
+            [Stmt::Expression(number(2.0)), Stmt::Expression(number(4.0))]
+        );
+        assert_eq!(
+            errors
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<String>>(),
+            [
+                "[line 1 column 1] Error: expected expression, found '+'. [E005]",
+                "[line 3 column 5] Error: expected expression, found ';'. [E005]",
+            ]
+        );
+    }
+
+    #[test]
+    fn synchronize_stops_before_statement_keyword() {
+        let tokens = lex_tokens("1 + + 2 print 3;");
+        assert_eq!(synchronize(&tokens)[0].tok, Tok::Print);
+        let tokens = lex_tokens("print print;");
+        assert_eq!(synchronize(&tokens)[0].tok, Tok::Print);
+        let tokens = lex_tokens("1 2; 3");
+        assert_eq!(synchronize(&tokens)[0].tok, Tok::Number(3.0));
+    }
+
+    #[test]
+    fn synchronize_skips_nested_blocks_and_stops_at_closing_brace() {
+        let tokens = lex_tokens("1 + { print 2; } 3 } print 4;");
+        assert_eq!(synchronize(&tokens)[0].tok, Tok::RightBrace);
+        assert_eq!(synchronize(&tokens).len(), 4);
+    }
+
+    fn error_strings(source: &str) -> Vec<String> {
+        let (_stmts, errors) = parse_program(&lex_tokens(source));
+        errors.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn one_error_in_function_body_is_reported_once() {
+        assert_eq!(
+            error_strings("fun f() {\n print 1 +;\n}"),
+            ["[line 2 column 11] Error: expected expression, found ';'. [E005]"]
+        );
+    }
+
+    #[test]
+    fn parsing_continues_after_error_in_block() {
+        let (stmts, errors) = parse_program(&lex_tokens("{ 1 + ; 2; }\n3;"));
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            stmts,
+            [
+                Stmt::Block(vec![Stmt::Expression(number(2.0))]),
+                Stmt::Expression(number(3.0))
+            ]
+        );
+    }
+
+    #[test]
+    fn error_before_closing_brace_closes_block() {
+        assert_eq!(
+            error_strings("{ print 1 + }\nprint 2;"),
+            ["[line 1 column 13] Error: expected expression, found '}'. [E005]"]
+        );
+    }
+
+    #[test]
+    fn errors_in_separate_methods_are_all_reported() {
+        assert_eq!(
+            error_strings("class A { f() { return +; } g() { var; } h() {} }"),
+            [
+                "[line 1 column 24] Error: expected expression, found '+'. [E005]",
+                "[line 1 column 38] Error: expected variable name, found ';'. [E005]",
+            ]
+        );
+    }
+
+    #[test]
+    fn bad_method_declaration_skips_to_next_member() {
+        let (stmts, errors) = parse_program(&lex_tokens("class A { 1 g() {} }\nprint 2;"));
+        assert_eq!(errors.len(), 1);
+        assert_eq!(stmts.len(), 2);
+    }
+
+    #[test]
+    fn error_at_end_inside_block_is_incomplete() {
+        assert_eq!(
+            error_strings("{ print 1 +"),
+            ["[end of input] Error: expected expression, found end of input. [E006]"]
+        );
+        assert!(is_incomplete(&lex_tokens("fun f() {\n  print 1 +")));
+    }
+
+    fn unary(op: UnaryOp, column: usize, expr: Expr) -> Expr {
+        Expr::Unary {
+            op,
+            place: at(column),
+            expr: Box::new(expr),
+        }
+    }
+
+    #[test]
+    fn unary_operators() {
+        assert_eq!(
+            parse_exactly("-5", parse_expression),
+            unary(UnaryOp::Negative, 1, number(5.0))
+        );
+        assert_eq!(
+            parse_exactly("!true", parse_expression),
+            unary(UnaryOp::Not, 1, Expr::Literal(Value::Bool(true)))
+        );
+    }
+
+    #[test]
+    fn repeated_unary_operators_nest() {
+        assert_eq!(
+            parse_exactly("--5", parse_expression),
+            unary(
+                UnaryOp::Negative,
+                1,
+                unary(UnaryOp::Negative, 2, number(5.0))
+            )
+        );
+        assert_eq!(
+            parse_exactly("!!true", parse_expression),
+            unary(
+                UnaryOp::Not,
+                1,
+                unary(UnaryOp::Not, 2, Expr::Literal(Value::Bool(true)))
+            )
+        );
+    }
+
+    #[test]
+    fn unary_binds_tighter_than_binary() {
+        assert_eq!(
+            parse_exactly("-1 * -2", parse_expression),
+            binary(
+                BinaryOp::Multiply,
+                4,
+                unary(UnaryOp::Negative, 1, number(1.0)),
+                unary(UnaryOp::Negative, 6, number(2.0))
+            )
+        );
+    }
+
+    #[test]
+    fn power_is_right_associative() {
+        use BinaryOp::Power;
+        assert_eq!(
+            parse_exactly("2 ** 3 ** 2", parse_expression),
+            binary(
+                Power,
+                3,
+                number(2.0),
+                binary(Power, 8, number(3.0), number(2.0))
+            )
+        );
+    }
+
+    #[test]
+    fn power_binds_tighter_than_multiply_and_unary() {
+        use BinaryOp::*;
+        assert_eq!(
+            parse_exactly("2 * 3 ** 2", parse_expression),
+            binary(
+                Multiply,
+                3,
+                number(2.0),
+                binary(Power, 7, number(3.0), number(2.0))
+            )
+        );
+        assert_eq!(
+            parse_exactly("-2 ** -1", parse_expression),
+            unary(
+                UnaryOp::Negative,
+                1,
+                binary(
+                    Power,
+                    4,
+                    number(2.0),
+                    unary(UnaryOp::Negative, 7, number(1.0))
+                )
+            )
+        );
+    }
+
+    fn logical(op: LogicalOp, column: usize, left: Expr, right: Expr) -> Expr {
+        Expr::Logical {
+            op,
+            place: at(column),
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+
+    fn boolean(b: bool) -> Expr {
+        Expr::Literal(Value::Bool(b))
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(
+            parse_exactly("true or false and nil", parse_expression),
+            logical(
+                LogicalOp::Or,
+                6,
+                boolean(true),
+                logical(
+                    LogicalOp::And,
+                    15,
+                    boolean(false),
+                    Expr::Literal(Value::Nil)
+                )
+            )
+        );
+        assert_eq!(
+            parse_exactly("true and false or nil", parse_expression),
+            logical(
+                LogicalOp::Or,
+                16,
+                logical(LogicalOp::And, 6, boolean(true), boolean(false)),
+                Expr::Literal(Value::Nil)
+            )
+        );
+    }
+
+    #[test]
+    fn logical_operators_are_left_associative() {
+        assert_eq!(
+            parse_exactly("true or false or nil", parse_expression),
+            logical(
+                LogicalOp::Or,
+                15,
+                logical(LogicalOp::Or, 6, boolean(true), boolean(false)),
+                Expr::Literal(Value::Nil)
+            )
+        );
+    }
+
+    #[test]
+    fn logical_operators_bind_looser_than_equality() {
+        assert_eq!(
+            parse_exactly("1 == 2 and 3 != 4", parse_expression),
+            logical(
+                LogicalOp::And,
+                8,
+                binary(BinaryOp::EqualEqual, 3, number(1.0), number(2.0)),
+                binary(BinaryOp::NotEqual, 14, number(3.0), number(4.0))
+            )
+        );
+    }
+
+    fn ternary(column: usize, cond: Expr, then_expr: Expr, else_expr: Expr) -> Expr {
+        Expr::Ternary {
+            place: at(column),
+            cond: Box::new(cond),
+            then_expr: Box::new(then_expr),
+            else_expr: Box::new(else_expr),
+        }
+    }
+
+    #[test]
+    fn ternary_is_right_associative() {
+        assert_eq!(
+            parse_exactly("1 ? 2 : 3 ? 4 : 5", parse_expression),
+            ternary(
+                3,
+                number(1.0),
+                number(2.0),
+                ternary(11, number(3.0), number(4.0), number(5.0))
+            )
+        );
+    }
+
+    #[test]
+    fn ternary_binds_looser_than_or() {
+        assert_eq!(
+            parse_exactly("true or false ? 1 + 2 : 3", parse_expression),
+            ternary(
+                15,
+                logical(LogicalOp::Or, 6, boolean(true), boolean(false)),
+                binary(BinaryOp::Plus, 19, number(1.0), number(2.0)),
+                number(3.0)
+            )
+        );
+    }
+
+    #[test]
+    fn nested_ternary_in_then_branch() {
+        assert_eq!(
+            parse_exactly("1 ? 2 ? 3 : 4 : 5", parse_expression),
+            ternary(
+                3,
+                number(1.0),
+                ternary(7, number(2.0), number(3.0), number(4.0)),
+                number(5.0)
+            )
+        );
+    }
+
+    #[test]
+    fn ternary_without_colon_is_error() {
+        assert_eq!(
+            parse_expression(&lex_tokens("true ? 1 2"))
+                .unwrap_err()
+                .to_string(),
+            "[line 1 column 10] Error: expected ':' to match '?' at line 1 column 6. [E007]"
+        );
+        assert_eq!(
+            parse_expression(&lex_tokens("true ? 1"))
+                .unwrap_err()
+                .to_string(),
+            "[end of input] Error: expected ':' to match '?' at line 1 column 6. [E007]"
+        );
+    }
+
+    #[test]
+    fn comma_is_left_associative() {
+        use BinaryOp::*;
+        assert_eq!(
+            parse_exactly("1, 2, 3", parse_expression),
+            binary(
+                Comma,
+                5,
+                binary(Comma, 2, number(1.0), number(2.0)),
+                number(3.0)
+            )
+        );
+    }
+
+    #[test]
+    fn comma_binds_loosest() {
+        use BinaryOp::*;
+        assert_eq!(
+            parse_exactly("1 ? 2 : 3, 4 + 5", parse_expression),
+            binary(
+                Comma,
+                10,
+                ternary(3, number(1.0), number(2.0), number(3.0)),
+                binary(Plus, 14, number(4.0), number(5.0))
+            )
+        );
+    }
 }
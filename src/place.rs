@@ -4,6 +4,9 @@
 
 use std::fmt;
 
+/// The default distance between tab stops, in columns.
+pub const DEFAULT_TAB_WIDTH: usize = 8;
+
 /// A (line, column) location in the source, for error reporting.
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub struct Place {
@@ -14,35 +17,54 @@ pub struct Place {
     ///
     /// Measured in chars.
     pub column: usize,
+
+    /// 0-based offset from the start of the source.
+    ///
+    /// Measured in bytes, so that it can be used to slice the source.
+    pub offset: usize,
 }
 
 impl Place {
     /// Construct a new Place (1,1), the start of a file.
     pub fn file_start() -> Place {
-        Place::new(1, 1)
+        Place::new(1, 1, 0)
     }
 
-    /// Construct a new Place at the given line, column.
-    pub fn new(line: usize, column: usize) -> Place {
+    /// Construct a new Place at the given line, column, and byte offset.
+    pub fn new(line: usize, column: usize, offset: usize) -> Place {
         assert!(line >= 1);
         assert!(column >= 1);
-        Place { line, column }
+        Place {
+            line,
+            column,
+            offset,
+        }
     }
 
     /// Advance by one character, accounting for tabs and newlines.
-    pub fn advance(&mut self, c: char) {
-        if c == '\n' {
+    ///
+    /// A lone `\r` counts as a newline. For `\r\n`, the caller should [Place::skip] the `\r`.
+    ///
+    /// Tabs advance the column to the next tab stop, every `tab_width` columns.
+    pub fn advance_with_tab(&mut self, c: char, tab_width: usize) {
+        debug_assert!(tab_width >= 1);
+        self.offset += c.len_utf8();
+        if c == '\n' || c == '\r' {
             self.line += 1;
             self.column = 1;
         } else if c == '\t' {
-            self.column += 1;
-            while self.column % 8 != 1 {
-                self.column += 1;
-            }
+            self.column = ((self.column - 1) / tab_width + 1) * tab_width + 1;
         } else {
             self.column += 1;
         }
     }
+
+    /// Advance past a character without moving to a new line or column.
+    ///
+    /// This is used for the `\r` in a `\r\n` pair, so that the pair counts as one newline.
+    pub fn skip(&mut self, c: char) {
+        self.offset += c.len_utf8();
+    }
 }
 
 impl fmt::Display for Place {
@@ -50,3 +72,39 @@ impl fmt::Display for Place {
         write!(f, "line {} column {}", self.line, self.column)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::Place;
+
+    fn columns_after_each_char(line: &str, tab_width: usize) -> Vec<usize> {
+        let mut place = Place::file_start();
+        line.chars()
+            .map(|c| {
+                place.advance_with_tab(c, tab_width);
+                place.column
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tab_width_8() {
+        assert_eq!(
+            columns_after_each_char("\ta\t12345678\t", 8),
+            [9, 10, 17, 18, 19, 20, 21, 22, 23, 24, 25, 33]
+        );
+    }
+
+    #[test]
+    fn tab_width_4() {
+        assert_eq!(
+            columns_after_each_char("\ta\t12345678\t", 4),
+            [5, 6, 9, 10, 11, 12, 13, 14, 15, 16, 17, 21]
+        );
+    }
+
+    #[test]
+    fn tab_width_1() {
+        assert_eq!(columns_after_each_char("\t\ta", 1), [2, 3, 4]);
+    }
+}
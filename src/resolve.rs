@@ -0,0 +1,732 @@
+// Copyright 2021 Martin Pool
+
+//! Resolve each use of a local variable to the scope where it's declared.
+//!
+//! This runs after parsing and before evaluation. It records on each variable reference how
+//! many scopes out the variable is declared, so that the interpreter finds the right
+//! variable even if another of the same name is later declared in a scope in between.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::ast::{Expr, FunctionDecl, Stmt};
+use crate::place::Place;
+
+/// An error found while resolving variables.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Error {
+    /// Place in the source where the error occurred.
+    pub place: Place,
+    /// Type of resolver error.
+    pub kind: ErrorKind,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{}] Error: {}. [{}]",
+            self.place,
+            self.kind,
+            self.kind.code()
+        )
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// A specific kind of resolver error.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ErrorKind {
+    /// A local variable is used in its own initializer.
+    ReadInOwnInitializer { name: String },
+    /// A local variable is declared twice in the same scope.
+    AlreadyDeclared { name: String },
+    /// `this` is used outside of a method.
+    ThisOutsideClass,
+    /// An initializer returns a value.
+    ReturnValueFromInitializer,
+    /// `super` is used outside of a class that has a superclass.
+    SuperOutsideSubclass,
+    /// A `break` or `continue` statement that is not inside a loop.
+    OutsideLoop { keyword: &'static str },
+    /// A `return` statement that is not inside a function.
+    ReturnOutsideFunction,
+    /// A variable declared with `const` is assigned.
+    AssignToConstant { name: String },
+}
+
+impl ErrorKind {
+    /// The diagnostic code for this kind of error, which can be looked up with `--explain`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ErrorKind::ReadInOwnInitializer { .. } => "E012",
+            ErrorKind::AlreadyDeclared { .. } => "E013",
+            ErrorKind::ThisOutsideClass => "E014",
+            ErrorKind::ReturnValueFromInitializer => "E015",
+            ErrorKind::SuperOutsideSubclass => "E016",
+            ErrorKind::OutsideLoop { .. } => "E009",
+            ErrorKind::ReturnOutsideFunction => "E011",
+            ErrorKind::AssignToConstant { .. } => "E018",
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorKind::ReadInOwnInitializer { name } => write!(
+                f,
+                "can't read local variable '{}' in its own initializer",
+                name
+            ),
+            ErrorKind::AlreadyDeclared { name } => {
+                write!(f, "already a variable called '{}' in this scope", name)
+            }
+            ErrorKind::ThisOutsideClass => write!(f, "can't use 'this' outside of a class"),
+            ErrorKind::ReturnValueFromInitializer => {
+                write!(f, "can't return a value from an initializer")
+            }
+            ErrorKind::SuperOutsideSubclass => {
+                write!(f, "can't use 'super' outside of a subclass")
+            }
+            ErrorKind::OutsideLoop { keyword } => write!(f, "{} outside of a loop", keyword),
+            ErrorKind::ReturnOutsideFunction => write!(f, "can't return from top-level code"),
+            ErrorKind::AssignToConstant { name } => {
+                write!(f, "cannot assign to constant '{}'", name)
+            }
+        }
+    }
+}
+
+/// Resolve all the variables in a program, returning any errors.
+pub fn resolve(stmts: &[Stmt]) -> Vec<Error> {
+    resolve_with_constants(stmts, &mut HashSet::new())
+}
+
+/// Resolve a program that runs in the same globals as earlier programs, which declared the
+/// global constants named in `global_constants`.
+///
+/// If there are no errors, `global_constants` is updated for the declarations in this
+/// program, ready for the next one.
+pub fn resolve_with_constants(
+    stmts: &[Stmt],
+    global_constants: &mut HashSet<String>,
+) -> Vec<Error> {
+    let mut resolver = Resolver {
+        global_constants: global_constants.clone(),
+        ..Resolver::default()
+    };
+    resolver.stmts(stmts);
+    if resolver.errors.is_empty() {
+        *global_constants = resolver.global_constants;
+    }
+    resolver.errors
+}
+
+#[derive(Default)]
+struct Resolver {
+    /// The local scopes from outermost to innermost, each mapping its variable names to
+    /// what's known about them. Globals are not tracked, except for constants.
+    scopes: Vec<HashMap<String, Local>>,
+    /// The names of global variables declared with `const`.
+    global_constants: HashSet<String>,
+    /// The kind of class whose methods are being resolved, which decides whether `this`
+    /// and `super` can be used.
+    class: ClassKind,
+    /// True while resolving the body of a class's `init` method, but not of functions
+    /// declared inside it.
+    in_initializer: bool,
+    /// True while resolving the body of any function.
+    in_function: bool,
+    /// The number of loops around the code being resolved, within the innermost function.
+    loop_depth: usize,
+    errors: Vec<Error>,
+}
+
+/// What the resolver knows about a local variable.
+#[derive(Clone, Copy, Debug, Default)]
+struct Local {
+    /// True once the variable's initializer has been resolved, so it's ready for use.
+    ready: bool,
+    /// True if the variable was declared with `const`.
+    constant: bool,
+}
+
+/// What kind of class, if any, encloses the code being resolved.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+enum ClassKind {
+    #[default]
+    None,
+    Class,
+    /// A class with a superclass.
+    Subclass,
+}
+
+impl Resolver {
+    fn stmts(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expression(expr)
+            | Stmt::Print {
+                value: Some(expr), ..
+            } => self.expr(expr),
+            Stmt::Print { value: None, .. } => (),
+            Stmt::Function(decl) => {
+                self.declare(&decl.name);
+                self.define(&decl.name);
+                self.function(decl);
+            }
+            Stmt::Class {
+                name,
+                superclass,
+                methods,
+            } => {
+                self.declare(name);
+                self.define(name);
+                let kind = if superclass.is_some() {
+                    ClassKind::Subclass
+                } else {
+                    ClassKind::Class
+                };
+                let enclosing_class = std::mem::replace(&mut self.class, kind);
+                // Methods of a subclass close over a scope where `super` is bound.
+                if let Some(superclass) = superclass {
+                    self.expr(superclass);
+                    self.scopes.push(HashMap::new());
+                    self.define("super");
+                }
+                // Methods are resolved inside a scope where `this` is bound, matching the
+                // environment made when a method is bound to an instance.
+                self.scopes.push(HashMap::new());
+                self.define("this");
+                for method in methods {
+                    self.function_with(method, method.name == "init");
+                }
+                self.scopes.pop();
+                if superclass.is_some() {
+                    self.scopes.pop();
+                }
+                self.class = enclosing_class;
+            }
+            Stmt::Block(stmts) => {
+                self.scopes.push(HashMap::new());
+                self.stmts(stmts);
+                self.scopes.pop();
+            }
+            Stmt::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                self.expr(cond);
+                self.stmt(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.stmt(else_branch);
+                }
+            }
+            Stmt::While {
+                cond,
+                body,
+                increment,
+            } => {
+                self.expr(cond);
+                self.loop_depth += 1;
+                self.stmt(body);
+                self.loop_depth -= 1;
+                if let Some(increment) = increment {
+                    self.expr(increment);
+                }
+            }
+            Stmt::Return { place, value } => {
+                if !self.in_function {
+                    self.errors.push(Error {
+                        place: *place,
+                        kind: ErrorKind::ReturnOutsideFunction,
+                    });
+                }
+                if let Some(value) = value {
+                    if self.in_initializer {
+                        self.errors.push(Error {
+                            place: *place,
+                            kind: ErrorKind::ReturnValueFromInitializer,
+                        });
+                    }
+                    self.expr(value);
+                }
+            }
+            Stmt::Break { place } | Stmt::Continue { place } if self.loop_depth == 0 => {
+                let keyword = match stmt {
+                    Stmt::Break { .. } => "'break'",
+                    _ => "'continue'",
+                };
+                self.errors.push(Error {
+                    place: *place,
+                    kind: ErrorKind::OutsideLoop { keyword },
+                });
+            }
+            Stmt::Break { .. } | Stmt::Continue { .. } => (),
+            Stmt::Var {
+                name,
+                place,
+                initializer,
+                constant,
+            } => {
+                if self.is_declared_in_innermost_scope(name) {
+                    self.errors.push(Error {
+                        place: *place,
+                        kind: ErrorKind::AlreadyDeclared { name: name.clone() },
+                    });
+                }
+                self.declare(name);
+                if let Some(initializer) = initializer {
+                    self.expr(initializer);
+                }
+                self.define_as(name, *constant);
+            }
+        }
+    }
+
+    /// Resolve a function body, in a new scope holding its parameters.
+    fn function(&mut self, decl: &FunctionDecl) {
+        self.function_with(decl, false);
+    }
+
+    /// Resolve a function or method body, which may be an initializer.
+    ///
+    /// The body is not inside any loop, even if the declaration is.
+    fn function_with(&mut self, decl: &FunctionDecl, is_initializer: bool) {
+        let enclosing_in_initializer = std::mem::replace(&mut self.in_initializer, is_initializer);
+        let enclosing_in_function = std::mem::replace(&mut self.in_function, true);
+        let enclosing_loop_depth = std::mem::replace(&mut self.loop_depth, 0);
+        self.scopes.push(HashMap::new());
+        for param in &decl.params {
+            self.declare(param);
+            self.define(param);
+        }
+        self.stmts(&decl.body);
+        self.scopes.pop();
+        self.in_initializer = enclosing_in_initializer;
+        self.in_function = enclosing_in_function;
+        self.loop_depth = enclosing_loop_depth;
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Literal(_) => (),
+            Expr::Variable { name, place, depth } => {
+                if self
+                    .scopes
+                    .last()
+                    .and_then(|scope| scope.get(name))
+                    .is_some_and(|local| !local.ready)
+                {
+                    self.errors.push(Error {
+                        place: *place,
+                        kind: ErrorKind::ReadInOwnInitializer { name: name.clone() },
+                    });
+                }
+                depth.set(self.depth(name));
+            }
+            Expr::Assign {
+                name,
+                place,
+                depth,
+                value,
+            } => {
+                self.expr(value);
+                depth.set(self.depth(name));
+                let constant = match depth.get() {
+                    Some(distance) => self.scopes[self.scopes.len() - 1 - distance][name].constant,
+                    None => self.global_constants.contains(name),
+                };
+                if constant {
+                    self.errors.push(Error {
+                        place: *place,
+                        kind: ErrorKind::AssignToConstant { name: name.clone() },
+                    });
+                }
+            }
+            Expr::This { place, depth } => {
+                if self.class == ClassKind::None {
+                    self.errors.push(Error {
+                        place: *place,
+                        kind: ErrorKind::ThisOutsideClass,
+                    });
+                }
+                depth.set(self.depth("this"));
+            }
+            Expr::Super { place, depth, .. } => {
+                if self.class != ClassKind::Subclass {
+                    self.errors.push(Error {
+                        place: *place,
+                        kind: ErrorKind::SuperOutsideSubclass,
+                    });
+                }
+                depth.set(self.depth("super"));
+            }
+            Expr::Function(decl) => self.function(decl),
+            Expr::Get { object, .. } => self.expr(object),
+            Expr::Set { object, value, .. } => {
+                self.expr(value);
+                self.expr(object);
+            }
+            Expr::Call { callee, args, .. } => {
+                self.expr(callee);
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            Expr::Grouping { expr } | Expr::Unary { expr, .. } => self.expr(expr),
+            Expr::Binary { left, right, .. } | Expr::Logical { left, right, .. } => {
+                self.expr(left);
+                self.expr(right);
+            }
+            Expr::Ternary {
+                cond,
+                then_expr,
+                else_expr,
+                ..
+            } => {
+                self.expr(cond);
+                self.expr(then_expr);
+                self.expr(else_expr);
+            }
+            Expr::Chain { first, rest } => {
+                self.expr(first);
+                for (_, _, operand) in rest {
+                    self.expr(operand);
+                }
+            }
+        }
+    }
+
+    /// Add a variable to the innermost scope, not yet ready for use.
+    fn declare(&mut self, name: &str) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_owned(), Local::default());
+        }
+    }
+
+    /// True if `name` is already declared in the innermost local scope.
+    ///
+    /// Globals can be redeclared, so this is always false at the top level.
+    fn is_declared_in_innermost_scope(&self, name: &str) -> bool {
+        self.scopes
+            .last()
+            .is_some_and(|scope| scope.contains_key(name))
+    }
+
+    /// Mark a variable in the innermost scope as ready for use.
+    fn define(&mut self, name: &str) {
+        self.define_as(name, false)
+    }
+
+    /// Mark a variable in the innermost scope as ready for use, and record whether it's a
+    /// constant, which is also tracked for globals.
+    fn define_as(&mut self, name: &str, constant: bool) {
+        match self.scopes.last_mut() {
+            Some(scope) => {
+                scope.insert(
+                    name.to_owned(),
+                    Local {
+                        ready: true,
+                        constant,
+                    },
+                );
+            }
+            None if constant => {
+                self.global_constants.insert(name.to_owned());
+            }
+            // Redeclaring a global replaces any constant of the same name.
+            None => {
+                self.global_constants.remove(name);
+            }
+        }
+    }
+
+    /// The number of scopes out to the innermost declaration of `name`, or None if it's not
+    /// declared in any local scope, and so must be global.
+    fn depth(&self, name: &str) -> Option<usize> {
+        self.scopes
+            .iter()
+            .rev()
+            .position(|scope| scope.contains_key(name))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::lex::lex;
+    use crate::parse::parse_program;
+
+    fn parse(source: &str) -> Vec<Stmt> {
+        let tokens: Vec<_> = lex(source).into_iter().map(Result::unwrap).collect();
+        let (stmts, errors) = parse_program(&tokens);
+        assert_eq!(errors, []);
+        stmts
+    }
+
+    /// The resolved depth of the variable in an expression statement.
+    fn depth_of(stmt: &Stmt) -> Option<usize> {
+        match stmt {
+            Stmt::Expression(Expr::Variable { depth, .. }) => depth.get(),
+            other => panic!("not a variable expression: {:?}", other),
+        }
+    }
+
+    fn block(stmt: &Stmt) -> &[Stmt] {
+        match stmt {
+            Stmt::Block(body) => body,
+            other => panic!("not a block: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn local_variable_depths() {
+        let stmts = parse("{ var a; a; { a; } }");
+        assert_eq!(resolve(&stmts), []);
+        let outer = block(&stmts[0]);
+        assert_eq!(depth_of(&outer[1]), Some(0));
+        assert_eq!(depth_of(&block(&outer[2])[0]), Some(1));
+    }
+
+    #[test]
+    fn global_variable_has_no_depth() {
+        let stmts = parse("var g; { g; }");
+        assert_eq!(resolve(&stmts), []);
+        assert_eq!(depth_of(&block(&stmts[1])[0]), None);
+    }
+
+    #[test]
+    fn read_in_own_initializer_is_error() {
+        let errors = resolve(&parse("var a = 1;\n{ var a = a + 1; }"));
+        assert_eq!(
+            errors
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<String>>(),
+            ["[line 2 column 11] Error: can't read local variable 'a' in its own initializer. [E012]"]
+        );
+    }
+
+    #[test]
+    fn local_redeclaration_is_error() {
+        let errors = resolve(&parse("{\n  var a = 1;\n  var a = 2;\n}"));
+        assert_eq!(
+            errors
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<String>>(),
+            ["[line 3 column 7] Error: already a variable called 'a' in this scope. [E013]"]
+        );
+    }
+
+    #[test]
+    fn parameter_redeclared_as_local_is_error() {
+        let errors = resolve(&parse("fun f(a) { var a; }"));
+        assert_eq!(
+            errors[0].kind,
+            ErrorKind::AlreadyDeclared {
+                name: "a".to_owned()
+            }
+        );
+    }
+
+    #[test]
+    fn shadowing_in_inner_scope_is_allowed() {
+        assert_eq!(resolve(&parse("{ var a = 1; { var a = 2; } }")), []);
+    }
+
+    #[test]
+    fn this_outside_class_is_error() {
+        for source in ["this;", "fun f() { return this; }"] {
+            assert_eq!(
+                resolve(&parse(source))
+                    .iter()
+                    .map(|err| err.kind.clone())
+                    .collect::<Vec<ErrorKind>>(),
+                [ErrorKind::ThisOutsideClass],
+                "{}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn this_in_method_is_allowed() {
+        assert_eq!(
+            resolve(&parse(
+                "class A { f() { fun g() { return this; } return this; } }"
+            )),
+            []
+        );
+    }
+
+    #[test]
+    fn return_value_from_initializer_is_error() {
+        let errors = resolve(&parse("class A {\n  init() { return 1; }\n}"));
+        assert_eq!(
+            errors
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<String>>(),
+            ["[line 2 column 12] Error: can't return a value from an initializer. [E015]"]
+        );
+    }
+
+    #[test]
+    fn bare_return_from_initializer_is_allowed() {
+        assert_eq!(
+            resolve(&parse(
+                "class A { init() { fun f() { return 1; } return; } other() { return 2; } }"
+            )),
+            []
+        );
+    }
+
+    #[test]
+    fn super_outside_subclass_is_error() {
+        for source in [
+            "super.f();",
+            "fun f() { super.f(); }",
+            "class A { f() { super.f(); } }",
+        ] {
+            assert_eq!(
+                resolve(&parse(source))
+                    .iter()
+                    .map(|err| err.kind.clone())
+                    .collect::<Vec<ErrorKind>>(),
+                [ErrorKind::SuperOutsideSubclass],
+                "{}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn super_in_subclass_is_allowed() {
+        assert_eq!(
+            resolve(&parse("class A {} class B < A { f() { return super.f; } }")),
+            []
+        );
+    }
+
+    #[test]
+    fn global_redeclaration_is_allowed() {
+        assert_eq!(resolve(&parse("var a = 1; var a = 2;")), []);
+    }
+
+    #[test]
+    fn global_may_refer_to_previous_global_in_initializer() {
+        assert_eq!(resolve(&parse("var a = 1; var a = a + 1;")), []);
+    }
+
+    fn error_strings(source: &str) -> Vec<String> {
+        resolve(&parse(source))
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn break_outside_loop_is_error() {
+        assert_eq!(
+            error_strings("break;\nif (true) continue;\nfor (;;) {} break;"),
+            [
+                "[line 1 column 1] Error: 'break' outside of a loop. [E009]",
+                "[line 2 column 11] Error: 'continue' outside of a loop. [E009]",
+                "[line 3 column 13] Error: 'break' outside of a loop. [E009]",
+            ]
+        );
+    }
+
+    #[test]
+    fn break_in_function_in_loop_is_error() {
+        assert_eq!(
+            error_strings("while (true) { fun f() { break; } }"),
+            ["[line 1 column 26] Error: 'break' outside of a loop. [E009]"]
+        );
+        assert_eq!(
+            error_strings("while (true) { var f = fun () { continue; }; }"),
+            ["[line 1 column 33] Error: 'continue' outside of a loop. [E009]"]
+        );
+    }
+
+    #[test]
+    fn jumps_inside_loops_and_functions_are_allowed() {
+        assert_eq!(
+            error_strings("fun f() { while (true) { if (true) break; continue; } return 1; }"),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn break_outside_loop_is_the_only_error() {
+        assert_eq!(
+            error_strings("fun f() { break; print 1; }"),
+            ["[line 1 column 11] Error: 'break' outside of a loop. [E009]"]
+        );
+    }
+
+    #[test]
+    fn return_outside_function_is_error() {
+        assert_eq!(
+            error_strings("return 1;\nwhile (true) return;\n{ return; }"),
+            [
+                "[line 1 column 1] Error: can't return from top-level code. [E011]",
+                "[line 2 column 14] Error: can't return from top-level code. [E011]",
+                "[line 3 column 3] Error: can't return from top-level code. [E011]",
+            ]
+        );
+    }
+
+    #[test]
+    fn constants_can_be_read() {
+        assert_eq!(
+            error_strings("const PI = 3.14;\nprint PI * 2;\n{ const E = 2.7; print E; }"),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn assignment_to_constant_is_error() {
+        assert_eq!(
+            error_strings("const PI = 3.14;\nPI = 3;\n{ const E = 2.7; fun f() { E = 1; } }"),
+            [
+                "[line 2 column 1] Error: cannot assign to constant 'PI'. [E018]",
+                "[line 3 column 28] Error: cannot assign to constant 'E'. [E018]",
+            ]
+        );
+    }
+
+    #[test]
+    fn variable_shadowing_constant_can_be_assigned() {
+        assert_eq!(
+            error_strings("const a = 1;\n{ var a = 2; a = 3; }\nvar a = 4;\na = 5;"),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn constants_are_remembered_between_programs() {
+        let mut constants = HashSet::new();
+        assert_eq!(
+            resolve_with_constants(&parse("const c = 1;"), &mut constants),
+            []
+        );
+        let errors = resolve_with_constants(&parse("c = 2;"), &mut constants);
+        assert_eq!(
+            errors[0].kind,
+            ErrorKind::AssignToConstant {
+                name: "c".to_owned()
+            }
+        );
+    }
+}
@@ -4,41 +4,108 @@
 //! and remembering the characters in each token.
 //!
 //! This layer knows nothing about the syntax of Lox, only how to generically scan a text file.
+//! The machinery is generic over the [Atom] being scanned, so it could equally scan a stream
+//! of tokens, but [Scan] is the usual case of scanning the chars of a string.
 
-use crate::place::Place;
+use std::ops::Deref;
 
-/// Scan characters with arbitrary lookahead.
+use crate::place::{Place, DEFAULT_TAB_WIDTH};
+
+/// Something that can be scanned, such as a char.
+pub trait Atom: Clone + PartialEq {
+    /// Accumulates the atoms in the current token, such as a `String` for chars.
+    type Token: Default + Extend<Self> + Deref;
+
+    /// True if this atom ends a line.
+    fn is_newline(&self) -> bool;
+
+    /// Move `place` past this atom, which is followed by `next`, if there is anything after it.
+    ///
+    /// By default each atom is one column wide and counts one towards the offset.
+    fn advance(&self, place: &mut Place, _next: Option<&Self>, _tab_width: usize) {
+        place.offset += 1;
+        if self.is_newline() {
+            place.line += 1;
+            place.column = 1;
+        } else {
+            place.column += 1;
+        }
+    }
+}
+
+impl Atom for char {
+    type Token = String;
+
+    fn is_newline(&self) -> bool {
+        *self == '\n' || *self == '\r'
+    }
+
+    /// Count tabs to the next tab stop, offsets in bytes, and `\r\n` as a single newline.
+    fn advance(&self, place: &mut Place, next: Option<&char>, tab_width: usize) {
+        if *self == '\r' && next == Some(&'\n') {
+            place.skip(*self);
+        } else {
+            place.advance_with_tab(*self, tab_width);
+        }
+    }
+}
+
+/// Scan the chars of a string.
+pub type Scan<'a> = Scanner<char, std::str::Chars<'a>>;
+
+/// Scan atoms with arbitrary lookahead.
 ///
 /// Provides low-level char parsing without knowing anything specific about the
 /// grammar.
-pub struct Scan<'a> {
-    input: std::str::Chars<'a>,
-    lookahead: Vec<char>,
-    current_token: String,
-    /// Location in the source of the character *about to be* taken.
+pub struct Scanner<C: Atom, I: Iterator<Item = C>> {
+    input: I,
+    lookahead: Vec<C>,
+    /// True once `input` has returned None.
+    exhausted: bool,
+    current_token: C::Token,
+    /// Location in the source of the atom *about to be* taken.
     next_place: Place,
     /// Location in the source of the token currently being recognized.
     token_start: Place,
+    /// Distance between tab stops, for calculating columns.
+    tab_width: usize,
 }
 
 impl<'a> Scan<'a> {
-    pub fn new(source: &'a str) -> Scan<'a> {
-        Scan {
-            input: source.chars(),
+    /// Construct a scanner that counts columns with tab stops every `tab_width` columns.
+    pub fn with_tab_width(source: &'a str, tab_width: usize) -> Scan<'a> {
+        assert!(tab_width >= 1);
+        Scanner {
+            tab_width,
+            ..Scanner::new(source.chars())
+        }
+    }
+}
+
+impl<C: Atom, I: Iterator<Item = C>> Scanner<C, I> {
+    /// Construct a scanner over any stream of atoms.
+    pub fn new(input: I) -> Scanner<C, I> {
+        let mut scanner = Scanner {
+            input,
             lookahead: Vec::new(),
-            current_token: String::new(),
+            exhausted: false,
+            current_token: C::Token::default(),
             next_place: Place::file_start(),
             token_start: Place::file_start(),
-        }
+            tab_width: DEFAULT_TAB_WIDTH,
+        };
+        // Look at the first atom so that [Scanner::is_empty] knows if there is any input.
+        scanner.peek();
+        scanner
     }
 
     pub fn start_token(&mut self) {
-        self.current_token.clear();
+        self.current_token = C::Token::default();
         self.token_start = self.next_place;
     }
 
-    /// Return all the atoms recognized since the last [Scan::start_token].
-    pub fn current_token(&self) -> &str {
+    /// Return all the atoms recognized since the last [Scanner::start_token].
+    pub fn current_token(&self) -> &<C::Token as Deref>::Target {
         &self.current_token
     }
 
@@ -47,53 +114,60 @@ impl<'a> Scan<'a> {
         self.token_start
     }
 
-    /// Return the 1-based column of the next character that will be returned by [Scan::take].
+    /// Return the [Place] of the next atom that will be returned by [Scanner::take], which
+    /// is just after the end of the current token.
+    pub fn next_place(&self) -> Place {
+        self.next_place
+    }
+
+    /// Return the 1-based column of the next atom that will be returned by [Scanner::take].
     pub fn next_column(&self) -> usize {
         self.next_place.column
     }
 
-    /// Consume and return one character.
+    /// Consume and return one atom.
     ///
     /// All consumption should go through here to maintain invariants, including
     /// line numbering and accumulating the current token.
     ///
     /// Returns None at the end of the input.
-    pub fn take(&mut self) -> Option<char> {
+    pub fn take(&mut self) -> Option<C> {
         let c = if self.lookahead.is_empty() {
             self.input.next()?
         } else {
             self.lookahead.remove(0)
         };
-        self.next_place.advance(c);
-        self.current_token.push(c);
+        let next = self.peek();
+        c.advance(&mut self.next_place, next.as_ref(), self.tab_width);
+        self.current_token.extend(Some(c.clone()));
         Some(c)
     }
 
-    /// Consume and return the next character if it matches a predicate,
+    /// Consume and return the next atom if it matches a predicate,
     /// otherwise leave it alone and return None.
     ///
     /// Returns None at the end of the input.
-    pub fn take_if<F>(&mut self, f: F) -> Option<char>
+    pub fn take_if<F>(&mut self, f: F) -> Option<C>
     where
-        F: Fn(&char) -> bool,
+        F: Fn(&C) -> bool,
     {
         self.peek().filter(|c| f(c)).and_then(|_c| self.take())
     }
 
-    /// Consume characters while they match a predicate.
+    /// Consume atoms while they match a predicate.
     ///
-    /// Consumed characters are accumulated into current_token but not returned.
+    /// Consumed atoms are accumulated into current_token but not returned.
     pub fn take_while<F>(&mut self, f: F)
     where
-        F: Fn(&char) -> bool,
+        F: Fn(&C) -> bool,
     {
         while self.take_if(&f).is_some() {}
     }
 
-    /// Take characters up to and including a terminator.
+    /// Take atoms up to and including a terminator.
     ///
-    /// Consumed characters are accumulated into current_token but not returned.
-    pub fn take_until(&mut self, f: fn(&char) -> bool) {
+    /// Consumed atoms are accumulated into current_token but not returned.
+    pub fn take_until(&mut self, f: fn(&C) -> bool) {
         while let Some(c) = self.take() {
             if f(&c) {
                 break;
@@ -101,39 +175,134 @@ impl<'a> Scan<'a> {
         }
     }
 
-    /// If the next character is `c` then consume it and return true;
+    /// If the next atom is `c` then consume it and return true;
     /// otherwise leave it alone and return false.
-    pub fn take_exactly(&mut self, c: char) -> bool {
+    pub fn take_exactly(&mut self, c: C) -> bool {
         self.take_if(|cc| *cc == c).is_some()
     }
 
-    /// Peek at the next character, if there is one, without consuming it.
-    pub fn peek(&mut self) -> Option<char> {
+    /// Peek at the next atom, if there is one, without consuming it.
+    pub fn peek(&mut self) -> Option<C> {
         self.peek_nth(0)
     }
 
-    /// Peek at the next two characters, if there are two more characters, without consuming them.
-    pub fn peek2(&mut self) -> Option<(char, char)> {
+    /// Peek at the next two atoms, if there are two more, without consuming them.
+    pub fn peek2(&mut self) -> Option<(C, C)> {
         if self.peek_nth(1).is_some() {
-            Some((self.lookahead[0], self.lookahead[1]))
+            Some((self.lookahead[0].clone(), self.lookahead[1].clone()))
         } else {
             None
         }
     }
 
-    fn peek_nth(&mut self, n: usize) -> Option<char> {
+    /// Peek at the atom `n` places ahead, if there is one, without consuming it.
+    ///
+    /// `peek_nth(0)` is the same as [Scanner::peek].
+    pub fn peek_nth(&mut self, n: usize) -> Option<C> {
         while self.lookahead.len() <= n {
             if let Some(c) = self.input.next() {
                 self.lookahead.push(c)
             } else {
+                self.exhausted = true;
                 return None;
             }
         }
-        Some(self.lookahead[n])
+        Some(self.lookahead[n].clone())
     }
 
     /// Return true if the scanner is at the end of the input.
-    pub fn is_empty(&mut self) -> bool {
-        self.peek().is_none()
+    ///
+    /// The scanner always looks one atom ahead after construction and after each
+    /// [Scanner::take], so this can be called through a shared reference.
+    pub fn is_empty(&self) -> bool {
+        self.lookahead.is_empty() && self.exhausted
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Atom, Scan, Scanner};
+    use crate::place::Place;
+
+    /// A made-up atom type: numbers, where zero ends a line.
+    impl Atom for u32 {
+        type Token = Vec<u32>;
+
+        fn is_newline(&self) -> bool {
+            *self == 0
+        }
+    }
+
+    /// Check for the end of input through a shared reference, as callers that only look at
+    /// the scanner should be able to.
+    fn at_end<C: Atom, I: Iterator<Item = C>>(scan: &Scanner<C, I>) -> bool {
+        scan.is_empty()
+    }
+
+    #[test]
+    fn is_empty_after_taking_everything() {
+        let mut scan = Scan::with_tab_width("ab", 8);
+        assert!(!at_end(&scan));
+        assert_eq!(scan.peek2(), Some(('a', 'b')));
+        assert!(!at_end(&scan));
+        assert_eq!(scan.take(), Some('a'));
+        assert!(!at_end(&scan));
+        assert_eq!(scan.take(), Some('b'));
+        assert!(at_end(&scan));
+        assert_eq!(scan.take(), None);
+        assert!(at_end(&scan));
+    }
+
+    #[test]
+    fn empty_input_is_empty() {
+        assert!(at_end(&Scan::with_tab_width("", 8)));
+    }
+
+    #[test]
+    fn is_empty_works_for_any_atom() {
+        let scan = Scanner::new(Vec::<u32>::new().into_iter());
+        assert!(at_end(&scan));
+        let mut scan = Scanner::new(vec![7u32].into_iter());
+        assert!(!at_end(&scan));
+        assert_eq!(scan.take(), Some(7));
+        assert!(at_end(&scan));
+    }
+
+    #[test]
+    fn offset_advances_through_take() {
+        let source = "a\u{e9}\n\u{1f600}z";
+        let mut scan = Scan::with_tab_width(source, 8);
+        assert_eq!(scan.next_place().offset, 0);
+        scan.peek2();
+        assert_eq!(scan.next_place().offset, 0);
+        let mut last = 0;
+        while scan.take().is_some() {
+            let offset = scan.next_place().offset;
+            assert!(offset > last);
+            last = offset;
+            assert_eq!(&source[..offset], scan.current_token());
+        }
+        assert_eq!(scan.next_place().offset, source.len());
+    }
+
+    #[test]
+    fn scan_numbers() {
+        let mut scan = Scanner::new(vec![1u32, 2, 3, 0, 4, 4, 5].into_iter());
+        assert_eq!(scan.peek2(), Some((1, 2)));
+        scan.take_while(|n| *n != 0);
+        assert_eq!(scan.current_token(), [1, 2, 3]);
+        assert_eq!(scan.next_place(), Place::new(1, 4, 3));
+        assert!(scan.take_exactly(0));
+        assert!(!scan.take_exactly(0));
+        assert_eq!(scan.next_place(), Place::new(2, 1, 4));
+        scan.start_token();
+        scan.take_until(|n| *n == 4);
+        assert_eq!(scan.current_token(), [4]);
+        assert_eq!(scan.token_start(), Place::new(2, 1, 4));
+        assert_eq!(scan.take_if(|n| *n == 4), Some(4));
+        assert_eq!(scan.take(), Some(5));
+        assert!(scan.is_empty());
+        assert_eq!(scan.current_token(), [4, 4, 5]);
+        assert_eq!(scan.next_column(), 4);
     }
 }
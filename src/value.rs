@@ -2,26 +2,218 @@
 
 //! Representable Lox values.
 
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::fmt;
+use std::rc::Rc;
 
+use anyhow::{anyhow, Error};
+
+use crate::ast::FunctionDecl;
+use crate::eval::{Environment, Interpreter};
 use crate::lex::{Tok, Token};
 
 /// Any type of Lox value.
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone)]
 pub enum Value {
     Nil,
     Bool(bool),
     String(String),
     Number(f64),
+    /// A function implemented in Rust.
+    NativeFn(Rc<NativeFn>),
+    /// A function declared in Lox.
+    Function(Rc<Function>),
+    /// A class, which makes a new instance when it's called.
+    Class(Rc<Class>),
+    /// An instance of a class.
+    Instance(Rc<Instance>),
+}
+
+/// Equality, as tested by Lox's `==`.
+///
+/// Values of different types are never equal. Numbers follow IEEE 754, so `0 == -0` but NaN is
+/// not equal to anything, including itself. Functions, classes, and instances are equal
+/// only if they're the same object.
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        use Value::*;
+        match (self, other) {
+            (Nil, Nil) => true,
+            (Bool(a), Bool(b)) => a == b,
+            (String(a), String(b)) => a == b,
+            (Number(a), Number(b)) => a == b,
+            (NativeFn(a), NativeFn(b)) => Rc::ptr_eq(a, b),
+            (Function(a), Function(b)) => Rc::ptr_eq(a, b),
+            (Class(a), Class(b)) => Rc::ptr_eq(a, b),
+            (Instance(a), Instance(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+/// A function declared in Lox, and the scope where it was declared, which it can see when
+/// it runs.
+pub struct Function {
+    pub decl: Rc<FunctionDecl>,
+    pub closure: Rc<RefCell<Environment>>,
+    /// True for a class's `init` method, which always returns `this`.
+    pub is_initializer: bool,
+}
+
+impl Function {
+    /// Make a method bound to `instance`, which it can see as `this`.
+    pub fn bind(&self, instance: Rc<Instance>) -> Function {
+        let mut env = Environment::with_enclosing(Rc::clone(&self.closure));
+        env.define("this", Value::Instance(instance));
+        Function {
+            decl: Rc::clone(&self.decl),
+            closure: Rc::new(RefCell::new(env)),
+            is_initializer: self.is_initializer,
+        }
+    }
+}
+
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Don't show the closure, which may contain the function itself.
+        write!(f, "Function({})", self.decl.name)
+    }
+}
+
+/// Functions are equal only if they're the same function.
+impl PartialEq for Function {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+/// A class declared in Lox.
+pub struct Class {
+    pub name: String,
+    /// The class this one inherits methods from, if any.
+    pub superclass: Option<Rc<Class>>,
+    pub methods: HashMap<String, Rc<Function>>,
+}
+
+impl fmt::Debug for Class {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Class({})", self.name)
+    }
+}
+
+impl Class {
+    /// Find a method declared in this class, or else inherited from its superclasses.
+    pub fn find_method(&self, name: &str) -> Option<&Rc<Function>> {
+        self.methods
+            .get(name)
+            .or_else(|| self.superclass.as_ref()?.find_method(name))
+    }
+}
+
+/// Classes are equal only if they're the same class.
+impl PartialEq for Class {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+/// An object made by calling a class, which has its own fields.
+///
+/// Instances are shared, so a change to a field is seen through every reference to the
+/// instance.
+pub struct Instance {
+    pub class: Rc<Class>,
+    fields: RefCell<HashMap<String, Value>>,
+}
+
+impl Instance {
+    /// Make a new instance of `class` with no fields.
+    pub fn new(class: Rc<Class>) -> Instance {
+        Instance {
+            class,
+            fields: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Get the value of a field, or None if it's not set.
+    pub fn get(&self, name: &str) -> Option<Value> {
+        self.fields.borrow().get(name).cloned()
+    }
+
+    /// Set a field, adding it if it doesn't exist yet.
+    pub fn set(&self, name: &str, value: Value) {
+        self.fields.borrow_mut().insert(name.to_owned(), value);
+    }
+}
+
+impl fmt::Debug for Instance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Don't show the fields, which may refer back to this instance.
+        write!(f, "Instance({})", self.class.name)
+    }
+}
+
+/// Instances are equal only if they're the same instance.
+impl PartialEq for Instance {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+/// A function implemented in Rust and callable from Lox.
+pub struct NativeFn {
+    pub name: &'static str,
+    /// The number of arguments it must be called with.
+    pub arity: usize,
+    /// The implementation, which gets the interpreter so that it can run more code.
+    pub fun: fn(&mut Interpreter<'_>, &[Value]) -> Result<Value, String>,
+}
+
+impl fmt::Debug for NativeFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "NativeFn({})", self.name)
+    }
+}
+
+/// Functions are equal only if they're the same function.
+impl PartialEq for NativeFn {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
 }
 
 impl Value {
+    /// True for every value except `nil` and `false`, including `0` and `""`.
+    ///
+    /// This decides which branch of a conditional is taken.
+    pub fn is_truthy(&self) -> bool {
+        use Value::*;
+        match self {
+            Nil | Bool(false) => false,
+            Bool(true) | Number(_) | String(_) | NativeFn(_) | Function(_) | Class(_)
+            | Instance(_) => true,
+        }
+    }
+
+    /// Logical not: true for `nil` and `false`, and false for every other value.
     pub fn not(&self) -> Value {
+        Value::Bool(!self.is_truthy())
+    }
+
+    /// The name of the type of this value, for use in error messages.
+    pub fn type_name(&self) -> &'static str {
         use Value::*;
         match self {
-            Nil => Nil,
-            Bool(b) => Bool(!b),
-            Number(_) | String(_) => unimplemented!(),
+            Nil => "nil",
+            Bool(_) => "bool",
+            String(_) => "string",
+            Number(_) => "number",
+            NativeFn(_) => "native fn",
+            Function(_) => "function",
+            Class(_) => "class",
+            Instance(_) => "instance",
         }
     }
 
@@ -61,20 +253,102 @@ impl From<f64> for Value {
     }
 }
 
+impl From<i64> for Value {
+    fn from(i: i64) -> Self {
+        Value::Number(i as f64)
+    }
+}
+
+impl From<u64> for Value {
+    fn from(u: u64) -> Self {
+        Value::Number(u as f64)
+    }
+}
+
+impl From<usize> for Value {
+    fn from(u: usize) -> Self {
+        Value::Number(u as f64)
+    }
+}
+
+impl TryFrom<Value> for f64 {
+    type Error = Error;
+
+    fn try_from(value: Value) -> Result<f64, Error> {
+        match value {
+            Value::Number(n) => Ok(n),
+            other => Err(mismatch("number", &other)),
+        }
+    }
+}
+
+impl TryFrom<Value> for String {
+    type Error = Error;
+
+    fn try_from(value: Value) -> Result<String, Error> {
+        match value {
+            Value::String(s) => Ok(s),
+            other => Err(mismatch("string", &other)),
+        }
+    }
+}
+
+impl TryFrom<Value> for bool {
+    type Error = Error;
+
+    fn try_from(value: Value) -> Result<bool, Error> {
+        match value {
+            Value::Bool(b) => Ok(b),
+            other => Err(mismatch("bool", &other)),
+        }
+    }
+}
+
+/// Construct an error for a value that is not of the expected type.
+fn mismatch(expected: &str, found: &Value) -> Error {
+    anyhow!("expected {}, found {}", expected, found.type_name())
+}
+
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Value::Nil => write!(f, "nil"),
             Value::Bool(b) => write!(f, "{}", b),
             Value::String(s) => write!(f, "{}", s),
-            Value::Number(n) => write!(f, "{}", n),
+            Value::NativeFn(_) => write!(f, "<native fn>"),
+            Value::Function(function) if function.decl.name.is_empty() => write!(f, "<fn>"),
+            Value::Function(function) => write!(f, "<fn {}>", function.decl.name),
+            Value::Class(class) => write!(f, "{}", class.name),
+            Value::Instance(instance) => write!(f, "{} instance", instance.class.name),
+            Value::Number(n) => {
+                // Rust already writes the shortest representation that reads back to the
+                // same number, with no trailing `.0` on integers.
+                //
+                // Very large and very small numbers are written with an exponent, as in
+                // JavaScript, rather than with hundreds of digits.
+                let magnitude = n.abs();
+                if n.is_nan() {
+                    write!(f, "nan")
+                } else if magnitude != 0.0
+                    && magnitude.is_finite()
+                    && !(1e-7..1e21).contains(&magnitude)
+                {
+                    write!(f, "{:e}", n)
+                } else {
+                    write!(f, "{}", n)
+                }
+            }
         }
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::Value;
+    use std::collections::HashMap;
+    use std::convert::TryFrom;
+    use std::rc::Rc;
+
+    use super::{Class, Instance, Value};
 
     #[test]
     fn display_value() {
@@ -92,4 +366,147 @@ mod test {
             assert_eq!(format!("{}", value), expected);
         }
     }
+
+    #[test]
+    fn truthiness() {
+        let cases = [
+            (Value::Nil, false),
+            (Value::Bool(false), false),
+            (Value::Bool(true), true),
+            (Value::Number(0.0), true),
+            (Value::Number(-1.5), true),
+            (Value::from(""), true),
+            (Value::from("false"), true),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(value.is_truthy(), expected, "{:?}", value);
+            assert_eq!(value.not(), Value::Bool(!expected), "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn instance_fields() {
+        let class = Rc::new(Class {
+            name: "Point".to_owned(),
+            superclass: None,
+            methods: HashMap::new(),
+        });
+        let instance = Instance::new(Rc::clone(&class));
+        assert_eq!(instance.get("x"), None);
+        instance.set("x", Value::from(1i64));
+        instance.set("x", Value::from(2i64));
+        assert_eq!(instance.get("x"), Some(Value::Number(2.0)));
+        assert_eq!(Value::Class(class).to_string(), "Point");
+        assert_eq!(
+            Value::Instance(Rc::new(instance)).to_string(),
+            "Point instance"
+        );
+    }
+
+    #[test]
+    fn number_equality() {
+        assert_eq!(Value::Number(1.5), Value::Number(1.5));
+        assert_eq!(Value::Number(0.0), Value::Number(-0.0));
+        assert_ne!(Value::Number(1.0), Value::Number(2.0));
+        assert_ne!(Value::Number(f64::NAN), Value::Number(f64::NAN));
+        let nan = Value::Number(f64::NAN);
+        assert_ne!(nan, nan.clone());
+        assert_eq!(Value::Number(f64::INFINITY), Value::Number(f64::INFINITY));
+        assert_ne!(Value::Number(1.0), Value::from("1"));
+        assert_ne!(Value::Nil, Value::Bool(false));
+    }
+
+    #[test]
+    fn objects_are_equal_only_to_themselves() {
+        let make_class = || {
+            Rc::new(Class {
+                name: "C".to_owned(),
+                superclass: None,
+                methods: HashMap::new(),
+            })
+        };
+        let class = make_class();
+        assert_eq!(
+            Value::Class(Rc::clone(&class)),
+            Value::Class(Rc::clone(&class))
+        );
+        assert_ne!(Value::Class(Rc::clone(&class)), Value::Class(make_class()));
+        let instance = Rc::new(Instance::new(Rc::clone(&class)));
+        assert_eq!(
+            Value::Instance(Rc::clone(&instance)),
+            Value::Instance(Rc::clone(&instance))
+        );
+        assert_ne!(
+            Value::Instance(instance),
+            Value::Instance(Rc::new(Instance::new(class)))
+        );
+    }
+
+    #[test]
+    fn from_integers() {
+        assert_eq!(Value::from(-12i64), Value::Number(-12.0));
+        assert_eq!(Value::from(1u64 << 40), Value::Number(1099511627776.0));
+        assert_eq!(Value::from(7usize), Value::Number(7.0));
+    }
+
+    #[test]
+    fn try_from_matching_variant() {
+        assert_eq!(f64::try_from(Value::Number(2.5)).unwrap(), 2.5);
+        assert_eq!(String::try_from(Value::from("hi")).unwrap(), "hi");
+        assert!(bool::try_from(Value::Bool(true)).unwrap());
+    }
+
+    #[test]
+    fn try_from_mismatched_variant() {
+        assert_eq!(
+            f64::try_from(Value::from("12")).unwrap_err().to_string(),
+            "expected number, found string"
+        );
+        assert_eq!(
+            String::try_from(Value::Nil).unwrap_err().to_string(),
+            "expected string, found nil"
+        );
+        assert_eq!(
+            bool::try_from(Value::Number(0.0)).unwrap_err().to_string(),
+            "expected bool, found number"
+        );
+    }
+
+    #[test]
+    fn display_canonical_numbers() {
+        let cases = [
+            (100.0, "100"),
+            (0.5, "0.5"),
+            (-2.0, "-2"),
+            (0.1 + 0.2, "0.30000000000000004"),
+            (1e21, "1e21"),
+            (f64::INFINITY, "inf"),
+            (f64::NAN, "nan"),
+        ];
+        for (n, expected) in cases {
+            assert_eq!(Value::Number(n).to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn display_extreme_numbers() {
+        let cases = [
+            (1e300, "1e300"),
+            (-1e300, "-1e300"),
+            (1e-300, "1e-300"),
+            (1.5e-10, "1.5e-10"),
+            (6.02214076e23, "6.02214076e23"),
+            (1e20, "100000000000000000000"),
+            (0.000001, "0.000001"),
+            (f64::INFINITY, "inf"),
+            (f64::NEG_INFINITY, "-inf"),
+        ];
+        for (n, expected) in cases {
+            let formatted = Value::Number(n).to_string();
+            assert_eq!(formatted, expected);
+            if n.is_finite() {
+                assert_eq!(formatted.parse::<f64>().unwrap(), n);
+            }
+        }
+    }
 }
@@ -0,0 +1,60 @@
+// Copyright 2021 Martin Pool
+
+//! Tests for the public library API, called directly rather than through the binary.
+
+use mbplox::{eval_with_output, lex, parse_program, run, Interpreter, Value};
+
+#[test]
+fn run_returns_last_expression_value() {
+    assert_eq!(run("1 + 2").unwrap(), Value::Number(3.0));
+    assert_eq!(run("var a = 1;").unwrap(), Value::Nil);
+}
+
+#[test]
+fn run_reports_errors() {
+    let err = run("-\"x\"").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "[line 1 column 1] Error: operand of '-' must be a number, not string."
+    );
+    assert!(err.is::<mbplox::RuntimeError>());
+}
+
+#[test]
+fn run_returns_compile_errors() {
+    let err = run("1 +; 2 +;").unwrap_err();
+    assert_eq!(err.to_string(), "2 parse error(s)");
+    let errors = err.downcast::<mbplox::CompileErrors>().unwrap();
+    assert_eq!(
+        errors.0[0].to_string(),
+        "[line 1 column 4] Error: expected expression, found ';'. [E005]"
+    );
+    assert_eq!(errors.0.len(), 2);
+}
+
+#[test]
+fn eval_with_output_captures_print() {
+    let mut out = Vec::new();
+    let value = eval_with_output("print \"hello\"; print 1 + 1; 3", &mut out).unwrap();
+    assert_eq!(value, Value::Number(3.0));
+    assert_eq!(String::from_utf8(out).unwrap(), "hello\n2\n");
+}
+
+#[test]
+fn interpreter_keeps_globals_between_evals() {
+    let mut interpreter = Interpreter::new();
+    assert_eq!(interpreter.eval("var a = 20;").unwrap(), None);
+    assert_eq!(
+        interpreter.eval("a + 1").unwrap(),
+        Some(Value::Number(21.0))
+    );
+}
+
+#[test]
+fn lex_and_parse_entry_points() {
+    let tokens: Vec<_> = lex("print 1;").into_iter().map(Result::unwrap).collect();
+    let (stmts, errors) = parse_program(&tokens);
+    assert!(errors.is_empty());
+    assert_eq!(stmts.len(), 1);
+    assert_eq!(stmts[0].tree(), "Print\n  Literal 1\n");
+}
@@ -7,7 +7,349 @@ mod common;
 use common::mbplox;
 
 #[test]
-fn error_if_no_args() {
-    // TODO: Later, this should start a repl instead of erroring.
-    mbplox().assert().failure();
+fn repl_evaluates_lines_from_stdin() {
+    mbplox()
+        .write_stdin("1 + 1\n")
+        .assert()
+        .success()
+        .stdout("2\n");
+}
+
+#[test]
+fn repl_echoes_expressions_but_not_declarations() {
+    mbplox()
+        .write_stdin("var x = 2;\n2 * 3\nfun f() {}\nx = x + 1;\nprint \"x\";\n")
+        .assert()
+        .success()
+        .stdout("6\n3\nx\n");
+}
+
+#[test]
+fn repl_continues_after_errors() {
+    let output = mbplox()
+        .write_stdin("1 +\n\n-\"x\"\n\"still\" + \" here\"\n")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "still here\n");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("[E006]"));
+    assert!(stderr.contains("[line 1 column 1] Error: operand of '-' must be a number"));
+}
+
+#[test]
+fn repl_reads_more_lines_until_input_is_complete() {
+    mbplox()
+        .write_stdin("fun add(a, b) {\n  return a +\n    b;\n}\nadd(1,\n2)\n")
+        .assert()
+        .success()
+        .stdout("3\n")
+        .stderr("");
+}
+
+#[test]
+fn repl_shows_configured_prompts() {
+    mbplox()
+        .args(["--prompt", "lox> ", "--continuation-prompt", ".. "])
+        .write_stdin("{\n  print 1;\n}\n")
+        .assert()
+        .success()
+        .stdout("lox> .. .. 1\nlox> \n")
+        .stderr("");
+}
+
+#[test]
+fn dash_can_be_a_prompt() {
+    mbplox()
+        .args(["--prompt", "-", "--continuation-prompt", "-"])
+        .write_stdin("{\n}\n")
+        .assert()
+        .success()
+        .stdout("---\n")
+        .stderr("");
+}
+
+#[test]
+fn repl_continuation_prompt_has_a_default() {
+    mbplox()
+        .args(["--prompt", "> "])
+        .write_stdin("{\n}\n")
+        .assert()
+        .success()
+        .stdout("> ... > \n")
+        .stderr("");
+}
+
+#[test]
+fn repl_reports_incomplete_input_at_end_of_stdin() {
+    let output = mbplox().write_stdin("print 1;\n{\n").output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "1\n");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Error: unclosed '{' opened at line 1 column 1. [E017]"));
+}
+
+#[test]
+fn dump_tokens_without_source_is_usage_error() {
+    mbplox().arg("--dump-tokens").assert().code(64);
+}
+
+#[test]
+fn explain_code() {
+    let output = mbplox().args(["--explain", "E002"]).output().unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("A string was still open at the end of the source."));
+}
+
+#[test]
+fn explain_unknown_code_is_usage_error() {
+    mbplox()
+        .args(["--explain", "E999"])
+        .assert()
+        .code(64)
+        .stdout("");
+}
+
+#[test]
+fn exit_value_from_number() {
+    mbplox()
+        .args(["--exit-value", "-e", "3"])
+        .assert()
+        .code(3)
+        .stdout("3\n");
+}
+
+#[test]
+fn exit_value_is_truncated_into_range() {
+    mbplox()
+        .args(["--exit-value", "-e", "3.9"])
+        .assert()
+        .code(3);
+    mbplox()
+        .args(["--exit-value", "-e", "1000"])
+        .assert()
+        .code(255);
+}
+
+#[test]
+fn exit_value_from_string_warns() {
+    let output = mbplox()
+        .args(["--exit-value", "-e", "\"three\""])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("warning: --exit-value: result is a string, not a number"));
+}
+
+#[test]
+fn dump_tokens_succeeds_without_lex_errors() {
+    mbplox()
+        .args(["--dump-tokens", "-e", "1 + 2"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn dump_tokens_fails_on_lex_errors() {
+    mbplox()
+        .args(["--dump-tokens", "-e", "1 @ 2"])
+        .assert()
+        .code(65);
+}
+
+#[test]
+fn runtime_error_is_reported_with_place() {
+    let output = mbplox().args(["-e", "1 + \"x\""]).output().unwrap();
+    assert_eq!(output.status.code(), Some(65));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains(
+        "[line 1 column 3] Error: operands of '+' must be two numbers or two strings, not number and string."
+    ));
+}
+
+#[test]
+fn errors_show_source_line_and_caret() {
+    let output = mbplox()
+        .args(["-e", "var a = 1;\nfoo(a | b);"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(65));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "[line 2 column 7] Error: unexpected character '|'. [E001]\n\
+         2 | foo(a | b);\n\
+         \x20 |       ^\n\
+         error: 1 lex error(s)\n"
+    );
+
+    let output = mbplox().args(["-e", "print -nil;"]).output().unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.ends_with("1 | print -nil;\n  |       ^\n"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn trailing_tokens_are_a_parse_error() {
+    let output = mbplox().args(["-e", "1 2"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(65));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr
+        .contains("[line 1 column 3] Error: expected ';' after expression, found '2'. [E005]"));
+    assert!(!stderr.contains("panicked"));
+}
+
+#[test]
+fn successful_run_writes_nothing_to_stderr() {
+    mbplox()
+        .args(["-e", "1 + 2; \"done\""])
+        .assert()
+        .success()
+        .stdout("done\n")
+        .stderr("");
+}
+
+#[test]
+fn syntax_error_in_file_is_data_error() {
+    mbplox()
+        .arg("testdata/errors/syntax_error.lox")
+        .assert()
+        .code(65);
+}
+
+#[test]
+fn missing_file_is_no_input_error() {
+    let output = mbplox().arg("testdata/no_such_file.lox").output().unwrap();
+    assert_eq!(output.status.code(), Some(66));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("error: failed to read testdata/no_such_file.lox"));
+}
+
+#[test]
+fn all_lex_errors_are_reported() {
+    let output = mbplox().args(["-e", "1 @ 2;\n3 # 4;"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(65));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("[line 1 column 3] Error: unexpected character '@'. [E001]"));
+    assert!(stderr.contains("[line 2 column 3] Error: unexpected character '#'. [E001]"));
+    assert!(stderr.contains("error: 2 lex error(s)"));
+    assert!(!stderr.contains("panicked"));
+}
+
+#[test]
+fn variables_persist_across_eval_arguments() {
+    mbplox()
+        .args(["-e", "var x = 1;", "-e", "print x;"])
+        .assert()
+        .success()
+        .stdout("1\n");
+}
+
+#[test]
+fn time_reports_phases_on_stderr() {
+    let output = mbplox()
+        .args(["--time", "testdata/stmt/print.lox"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let expected = mbplox().arg("testdata/stmt/print.lox").output().unwrap();
+    assert_eq!(output.stdout, expected.stdout);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    for phase in ["lex:", "parse:", "resolve:", "eval:"] {
+        assert!(
+            stderr.contains(phase),
+            "missing {:?} in {:?}",
+            phase,
+            stderr
+        );
+    }
+}
+
+#[test]
+fn check_valid_file_runs_nothing() {
+    mbplox()
+        .args(["--check", "testdata/stmt/print.lox"])
+        .assert()
+        .success()
+        .stdout("")
+        .stderr("");
+}
+
+#[test]
+fn check_reports_errors_in_each_source() {
+    let output = mbplox()
+        .args(["--check", "testdata/errors/syntax_error.lox"])
+        .args(["-e", "print \"side effect\";", "-e", "{ var a = a; }"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(65));
+    assert_eq!(output.stdout, b"");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Error: expected expression, found ';'. [E005]"));
+    assert!(stderr.contains("[E012]"));
+}
+
+#[test]
+fn check_does_not_catch_runtime_errors() {
+    mbplox()
+        .args(["--check", "testdata/errors/divide_string.lox"])
+        .assert()
+        .success()
+        .stdout("");
+}
+
+#[test]
+fn bare_print_prints_blank_line() {
+    mbplox()
+        .args(["-e", "print; print 1;"])
+        .assert()
+        .success()
+        .stdout("\n1\n");
+}
+
+#[test]
+fn math_chains_option() {
+    mbplox()
+        .args(["--math-chains", "-e", "print 1 < 2 < 3; print 3 < 2 < 1;"])
+        .assert()
+        .success()
+        .stdout("true\nfalse\n");
+    let output = mbplox().args(["-e", "print 1 < 2 < 3;"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(65));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains(
+        "[line 1 column 13] Error: operands of '<' must be numbers, not bool and number."
+    ));
+}
+
+#[test]
+fn dash_reads_source_from_stdin() {
+    mbplox()
+        .arg("-")
+        .write_stdin("var a = 5;\nprint a * 2;\n")
+        .assert()
+        .success()
+        .stdout("10\n");
+}
+
+#[test]
+fn dash_is_run_before_eval_arguments() {
+    mbplox()
+        .args(["-e", "print a + 1;", "-"])
+        .write_stdin("var a = 5;\n")
+        .assert()
+        .success()
+        .stdout("6\n");
+}
+
+#[test]
+fn dash_and_file_name_is_usage_error() {
+    mbplox()
+        .args(["-", "testdata/stmt/print.lox"])
+        .assert()
+        .code(64);
 }
@@ -6,8 +6,18 @@
 //!
 //! * `// expect: ` comments, whose text should be produced on stdout.
 //!
+//! * An `// exit-code: ` comment, giving the expected exit code of the interpreter. Otherwise, it
+//!   should succeed, unless an error is expected.
+//!
+//! * An `// expect runtime error: ` comment, whose text should be in stderr. The interpreter
+//!   should then fail.
+//!
+//! * `// [line N] Error: MESSAGE` comments, in the style of the Crafting Interpreters test suite.
+//!   If there are any, the interpreter should fail, and the errors on stderr should be exactly
+//!   these, with the same line numbers and messages.
+//!
 //! * `#! ` shebang lines, supplying args for the interpreter. The first word should be `mbplox`,
-//! but the interpreter is actually found in the Cargo build directory.
+//!   but the interpreter is actually found in the Cargo build directory.
 
 #![cfg(test)]
 
@@ -45,6 +55,42 @@ struct Case {
     path: PathBuf,
     /// Expected output lines.
     output: Vec<String>,
+    /// Expected exit code, if given explicitly.
+    exit_code: Option<i32>,
+    /// Expected runtime error message.
+    runtime_error: Option<String>,
+    /// Expected errors with line numbers.
+    errors: Vec<ExpectedError>,
+}
+
+/// An error expected on a particular line.
+#[derive(Debug, Clone, PartialEq)]
+struct ExpectedError {
+    line: usize,
+    message: String,
+}
+
+impl ExpectedError {
+    /// Parse text like `[line 4] Error at ';': message`, returning None if it's not in that form.
+    ///
+    /// Anything between `]` and the first `: ` is ignored, as is a trailing diagnostic code, so
+    /// this accepts both the Crafting Interpreters form and mbplox's own
+    /// `[line 4 column 5] Error: message. [E005]`.
+    fn parse(s: &str) -> Option<ExpectedError> {
+        let rest = s.strip_prefix("[line ")?;
+        let line_end = rest.find(|c: char| !c.is_ascii_digit())?;
+        let line = rest[..line_end].parse().ok()?;
+        let (_, after_place) = rest.split_once("] ")?;
+        let (_, message) = after_place.split_once(": ")?;
+        let message = match message.rsplit_once(" [E") {
+            Some((message, code)) if code.ends_with(']') => message,
+            _ => message,
+        };
+        Some(ExpectedError {
+            line,
+            message: message.to_owned(),
+        })
+    }
 }
 
 impl Case {
@@ -54,9 +100,21 @@ impl Case {
         let source = fs::read_to_string(&path).unwrap();
 
         let mut output = Vec::new();
+        let mut exit_code = None;
+        let mut runtime_error = None;
+        let mut errors = Vec::new();
         for l in source.lines() {
             if let Some((_, expectation)) = l.split_once("// expect: ") {
                 output.push(expectation.to_owned())
+            } else if let Some((_, message)) = l.split_once("// expect runtime error: ") {
+                runtime_error = Some(message.to_owned());
+            } else if let Some((_, code)) = l.split_once("// exit-code: ") {
+                exit_code = Some(code.trim().parse().expect("parse exit code"));
+            } else if let Some((_, error)) = l.split_once("// [line ") {
+                errors.push(
+                    ExpectedError::parse(&format!("[line {}", error))
+                        .expect("parse expected error"),
+                );
             }
         }
 
@@ -67,7 +125,14 @@ impl Case {
             args = words.map(|w| w.to_owned()).collect();
         }
 
-        Case { path, output, args }
+        Case {
+            path,
+            output,
+            args,
+            exit_code,
+            runtime_error,
+            errors,
+        }
     }
 
     /// Run mbplox on a file with given arguments, and check that the output matches the expectations
@@ -79,10 +144,29 @@ impl Case {
             .arg(&self.path)
             .output()
             .unwrap();
-        if !output.stderr.is_empty() {
-            println!("{}", String::from_utf8_lossy(&output.stderr));
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if !stderr.is_empty() {
+            println!("{}", stderr);
+        }
+        if let Some(exit_code) = self.exit_code {
+            assert_eq!(output.status.code(), Some(exit_code));
+        } else if self.runtime_error.is_some() || !self.errors.is_empty() {
+            assert!(!output.status.success());
+        } else {
+            assert_eq!(output.status.code(), Some(0));
+        }
+        if let Some(message) = &self.runtime_error {
+            assert!(
+                stderr.contains(message.as_str()),
+                "stderr should contain {:?}",
+                message
+            );
+        }
+        if !self.errors.is_empty() {
+            let emitted: Vec<ExpectedError> =
+                stderr.lines().filter_map(ExpectedError::parse).collect();
+            assert_eq!(emitted, self.errors);
         }
-        assert!(output.status.success());
         // Possibly this should compare the multi-line strings, rather than lists of strings, but that
         // would need more care to work consistently on Windows...
         let output_string = String::from_utf8(output.stdout).unwrap(); // hold the str